@@ -1,70 +1,1334 @@
+use crate::audit::{self, AdminCommand};
+use crate::birthday;
+use crate::db::{BirthdayConfig, CommandPermission, LinkRuleOverride};
+use crate::linkfix::{self, LinkFixMode, LinkRule, RuleSource};
+use crate::misc;
+use crate::scheduler::{CatchUp, Job, JobKind, NewJob, Schedule};
+use crate::timeparse;
+use crate::utils;
 use crate::{Context, Error};
-use chrono::{Datelike, NaiveDate};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono_tz::Tz;
 use poise::CreateReply;
-use serenity::all::MessageId;
-use std::collections::HashMap;
-use std::fs::File;
-use tokio::time::{sleep, Duration};
-
-#[poise::command(slash_command, guild_only)]
-pub async fn start_birthday_reminders(ctx: Context<'_>) -> Result<(), Error> {
-    let http = ctx.serenity_context().http.clone(); // Get a cloned Arc<Http>
-    let channel_id = ctx.channel_id();
-
-    tokio::spawn(async move {
-        loop {
-            // Load the CSV file
-            let file = match File::open("birthdays.csv") {
-                Ok(file) => file,
-                Err(err) => {
-                    println!("Error opening CSV file: {:?}", err);
-                    continue;
-                }
-            };
+use serenity::all::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAllowedMentions,
+    CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage, GuildChannel, MessageId, Permissions, Role,
+};
+use std::time::Duration;
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands(
+        "birthday_reminders_start",
+        "birthday_reminders_status",
+        "birthday_reminders_stop"
+    ),
+    subcommand_required
+)]
+pub async fn birthday_reminders(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "start", ephemeral)]
+pub async fn birthday_reminders_start(
+    ctx: Context<'_>,
+    #[description = "Where to post greetings (default this channel)"]
+    #[channel_types("Text", "News")]
+    channel: Option<GuildChannel>,
+    #[description = "Local hour in each member's timezone to send greetings (default 0, midnight)"]
+    #[max = 23]
+    hour: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+    let hour = hour.unwrap_or(0);
+
+    // The scheduled job reads this each time it runs
+    ctx.data()
+        .db
+        .set_birthday_config(BirthdayConfig {
+            guild_id,
+            channel_id,
+            enabled: true,
+            hour,
+        })
+        .await?;
+    birthday::start_reminders(&ctx.data().scheduler, guild_id).await?;
+
+    ctx.send(CreateReply::default().content(format!(
+        "Birthday greetings will be posted in <#{}> at {:02}:00 in each member's timezone.",
+        channel_id, hour
+    )))
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "status", ephemeral)]
+pub async fn birthday_reminders_status(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let config = ctx.data().db.get_birthday_config(guild_id).await?;
+    let next_run = birthday::next_reminder_run(&ctx.data().scheduler, guild_id).await?;
+
+    let content = match (config, next_run) {
+        (None, _) => "Birthday reminders aren't set up. Use `/birthday_reminders start`.".to_owned(),
+        (Some(config), Some(next_run)) if config.enabled => format!(
+            "Birthday reminders are running, posting in <#{}> at {:02}:00 in each member's timezone. Next check {}.",
+            config.channel_id,
+            config.hour,
+            utils::discord_timestamp(next_run.timestamp(), 'R')
+        ),
+        (Some(config), None) if config.enabled => format!(
+            "Birthday reminders are turned on for <#{}> but aren't running. Use `/birthday_reminders start` to restart them.",
+            config.channel_id
+        ),
+        (Some(config), _) => format!(
+            "Birthday reminders are stopped. They last posted in <#{}> at {:02}:00.",
+            config.channel_id, config.hour
+        ),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "stop", ephemeral)]
+pub async fn birthday_reminders_stop(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let was_enabled = ctx.data().db.disable_birthday_reminders(guild_id).await?;
+    let was_running = birthday::stop_reminders(&ctx.data().scheduler, guild_id).await?;
+    // Nothing would take back roles handed out today otherwise
+    if let Err(err) =
+        birthday::revoke_all_birthday_roles(ctx.http(), &ctx.data().db, guild_id).await
+    {
+        println!("Error removing birthday roles in {}: {:?}", guild_id, err);
+    }
+
+    let content = if was_enabled || was_running {
+        "Stopped birthday reminders."
+    } else {
+        "Birthday reminders weren't running."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands(
+        "birthday_messages_add",
+        "birthday_messages_remove",
+        "birthday_messages_list",
+        "birthday_messages_preview",
+        "birthday_messages_dm"
+    ),
+    subcommand_required
+)]
+pub async fn birthday_messages(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+// Fill in a greeting with the author as the birthday member.
+// Age placeholders are shown as for a 30th birthday.
+fn sample_greeting(ctx: Context<'_>, template: &str) -> String {
+    let fields = birthday::GreetingFields {
+        mention: format!("<@{}>", ctx.author().id),
+        name: ctx.author().display_name().to_owned(),
+        age: Some(30),
+    };
+    birthday::render_greeting(template, &fields)
+}
+
+fn placeholder_help() -> String {
+    format!(
+        "Placeholders: {}. Messages using the age ones are only picked for members who saved their birth year and chose to share their age.",
+        birthday::PLACEHOLDERS.join(", ")
+    )
+}
+
+#[poise::command(slash_command, guild_only, rename = "add", ephemeral)]
+pub async fn birthday_messages_add(
+    ctx: Context<'_>,
+    #[description = "The greeting, e.g. \"Happy birthday {mention}, enjoy your {ordinal_age}!\""]
+    #[max_length = 1500]
+    message: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    if let Err(problem) = birthday::check_template(&message) {
+        ctx.send(CreateReply::default().content(format!("{}\n{}", problem, placeholder_help())))
+            .await?;
+        return Ok(());
+    }
+
+    // Show what it will look like before saving it
+    let save_id = format!("{}-save", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&save_id)
+            .label("Save")
+            .style(ButtonStyle::Success),
+        CreateButton::new(&cancel_id)
+            .label("Cancel")
+            .style(ButtonStyle::Secondary),
+    ]);
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(format!("Preview:\n\n{}", sample_greeting(ctx, &message)))
+                .components(vec![buttons])
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    let ctx_id = ctx.id();
+    let press = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(5 * 60))
+        .await;
+
+    let content = match &press {
+        Some(press) if press.data.custom_id == save_id => {
+            let id = ctx
+                .data()
+                .db
+                .add_birthday_template(guild_id, &message)
+                .await?;
+            format!("Saved as message #{}.", id)
+        }
+        Some(_) => "Cancelled, nothing was saved.".to_owned(),
+        None => "Timed out, nothing was saved.".to_owned(),
+    };
+
+    match press {
+        Some(press) => {
+            press
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(content)
+                            .components(Vec::new()),
+                    ),
+                )
+                .await?
+        }
+        None => {
+            reply
+                .edit(
+                    ctx,
+                    CreateReply::default()
+                        .content(content)
+                        .components(Vec::new()),
+                )
+                .await?
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "remove", ephemeral)]
+pub async fn birthday_messages_remove(
+    ctx: Context<'_>,
+    #[description = "The message number, as shown by /birthday_messages list"] id: i64,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let content = if ctx.data().db.remove_birthday_template(guild_id, id).await? {
+        format!("Removed message #{}.", id)
+    } else {
+        format!("There's no message #{}.", id)
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "list", ephemeral)]
+pub async fn birthday_messages_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let templates = ctx.data().db.get_birthday_templates(guild_id).await?;
+
+    let description = if templates.is_empty() {
+        let defaults = birthday::greeting_pool(&ctx.data().db, guild_id).await?;
+        format!(
+            "This server has no messages of its own, so one of these is picked at random:\n{}",
+            defaults
+                .iter()
+                .map(|template| format!("- `{}`", template))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    } else {
+        templates
+            .iter()
+            .map(|(id, template)| format!("**#{}** `{}`", id, template))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let dm = ctx.data().db.get_birthday_dm(guild_id).await?;
+    ctx.send(
+        CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("🎂 Birthday messages")
+                .description(description)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Also sent by DM: {}",
+                    if dm { "yes" } else { "no" }
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "preview", ephemeral)]
+pub async fn birthday_messages_preview(
+    ctx: Context<'_>,
+    #[description = "A greeting to try out without saving it"]
+    #[max_length = 1500]
+    message: String,
+) -> Result<(), Error> {
+    let content = match birthday::check_template(&message) {
+        Ok(()) => format!("Preview:\n\n{}", sample_greeting(ctx, &message)),
+        Err(problem) => format!("{}\n{}", problem, placeholder_help()),
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "dm", ephemeral)]
+pub async fn birthday_messages_dm(
+    ctx: Context<'_>,
+    #[description = "Also send members their greeting by DM"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data().db.set_birthday_dm(guild_id, enabled).await?;
+
+    let content = if enabled {
+        "Members will also get their birthday greeting by DM."
+    } else {
+        "Birthday greetings are only posted in the channel now."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands(
+        "settings_time_detection",
+        "settings_dst_channel",
+        "settings_birthday_role",
+        "settings_anniversaries",
+        "settings_link_fixing",
+        "settings_link_previews"
+    ),
+    subcommand_required
+)]
+pub async fn settings(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "time_detection", ephemeral)]
+pub async fn settings_time_detection(
+    ctx: Context<'_>,
+    #[description = "Offer timestamp conversions for times mentioned in chat"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data().db.set_time_detection(guild_id, enabled).await?;
+
+    let content = if enabled {
+        "Time detection is now on. Members with a registered timezone get a 🕒 reaction on messages mentioning a time."
+    } else {
+        "Time detection is now off."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "dst_channel", ephemeral)]
+pub async fn settings_dst_channel(
+    ctx: Context<'_>,
+    #[description = "Where to announce daylight saving changes (leave empty to turn off)"]
+    #[channel_types("Text", "News")]
+    channel: Option<GuildChannel>,
+    #[description = "How many days ahead to warn (default 3)"]
+    #[min = 1]
+    #[max = 14]
+    notice_days: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let notice_days = notice_days.unwrap_or(3);
+    ctx.data()
+        .db
+        .set_dst_channel(
+            guild_id,
+            channel.as_ref().map(|channel| channel.id),
+            notice_days,
+        )
+        .await?;
+
+    let content = match channel {
+        Some(channel) => format!(
+            "Daylight saving changes in members' timezones will be announced in <#{}>, {} days ahead.",
+            channel.id, notice_days
+        ),
+        None => "Daylight saving announcements are now off.".to_owned(),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "birthday_role", ephemeral)]
+pub async fn settings_birthday_role(
+    ctx: Context<'_>,
+    #[description = "Role to give members for their birthday (leave empty to turn off)"]
+    role: Option<Role>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data()
+        .db
+        .set_birthday_role(guild_id, role.as_ref().map(|role| role.id))
+        .await?;
+
+    let content = match role {
+        Some(role) => format!(
+            "Members get <@&{}> for 24 hours from their birthday greeting. My own role must be above it in the role list.",
+            role.id
+        ),
+        None => "Members no longer get a birthday role.".to_owned(),
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "anniversaries", ephemeral)]
+pub async fn settings_anniversaries(
+    ctx: Context<'_>,
+    #[description = "Announce the yearly server anniversaries of members who opt in"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data()
+        .db
+        .set_anniversaries_enabled(guild_id, enabled)
+        .await?;
+
+    // Anniversaries go out with the birthday greetings, so they need those running
+    let reminders_on = ctx
+        .data()
+        .db
+        .get_birthday_config(guild_id)
+        .await?
+        .is_some_and(|config| config.enabled);
+    let content = if !enabled {
+        "Server anniversaries are no longer announced."
+    } else if reminders_on {
+        "Server anniversaries of members who use `/anniversary opt_in` will be announced alongside birthdays."
+    } else {
+        "Server anniversaries of members who use `/anniversary opt_in` will be announced alongside birthdays, but birthday reminders are off. Turn them on with `/birthday_reminders start` for anything to be announced."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "link_fixing", ephemeral)]
+pub async fn settings_link_fixing(
+    ctx: Context<'_>,
+    #[description = "What to do with links posted in chat that don't embed well"] mode: LinkFixMode,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data().db.set_link_fixing(guild_id, mode).await?;
+
+    let content = match mode {
+        LinkFixMode::Off => "Links posted in chat are no longer fixed.",
+        LinkFixMode::Reply => "I'll reply to links posted in chat with versions that embed properly. See `/link_rules list` for the sites covered. Members can opt out with `/link_fixing opt_out`.",
+        LinkFixMode::Replace => "I'll reply to links posted in chat with versions that embed properly, and hide the original embeds. I need Manage Messages for that. See `/link_rules list` for the sites covered. Members can opt out with `/link_fixing opt_out`.",
+        LinkFixMode::Embed => "I'll reply to twitter/x posts with embeds of my own and to other links with versions that embed properly, and hide the original embeds. I need Manage Messages for that. See `/link_rules list` for the sites covered. Members can opt out with `/link_fixing opt_out`.",
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "link_previews", ephemeral)]
+pub async fn settings_link_previews(
+    ctx: Context<'_>,
+    #[description = "Answer Goodreads, IMDb, TMDb and Letterboxd links with a card"] enabled: bool,
+    #[description = "The channel to change (default this channel)"]
+    #[channel_types("Text", "News")]
+    channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+    ctx.data()
+        .db
+        .set_link_previews(guild_id, channel_id, enabled)
+        .await?;
+
+    let content = if enabled {
+        format!(
+            "Goodreads, IMDb, TMDb and Letterboxd links posted in <#{}> will be answered with a card for the book or film.",
+            channel_id
+        )
+    } else {
+        format!("Links posted in <#{}> no longer get cards.", channel_id)
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+// Paginated list of scheduled jobs, ten to a page
+async fn send_job_list(ctx: Context<'_>, title: &str, jobs: &[Job]) -> Result<(), Error> {
+    let lines: Vec<String> = jobs
+        .iter()
+        .map(|job| {
+            let created_by = job
+                .created_by
+                .map(|user_id| format!(", by <@{}>", user_id))
+                .unwrap_or_default();
+            format!(
+                "**#{}** {}, {}{}. Next {}",
+                job.id,
+                job.kind.describe(),
+                job.schedule.describe(),
+                created_by,
+                utils::discord_timestamp(job.next_run.timestamp(), 'R')
+            )
+        })
+        .collect();
+
+    let pages: Vec<String> = lines.chunks(10).map(|chunk| chunk.join("\n")).collect();
+    let page_count = pages.len();
+    let embeds = pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, page)| {
+            CreateEmbed::default()
+                .title(title)
+                .description(page)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Page {}/{}",
+                    index + 1,
+                    page_count
+                )))
+        })
+        .collect();
+
+    utils::paginate_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands("jobs_list", "jobs_cancel"),
+    subcommand_required
+)]
+pub async fn jobs(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "list", ephemeral)]
+pub async fn jobs_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let jobs = ctx.data().scheduler.guild_jobs(guild_id).await?;
+    if jobs.is_empty() {
+        ctx.send(CreateReply::default().content("Nothing is scheduled in this server."))
+            .await?;
+        return Ok(());
+    }
+
+    send_job_list(ctx, "Scheduled jobs", &jobs).await
+}
+
+#[poise::command(slash_command, guild_only, rename = "cancel", ephemeral)]
+pub async fn jobs_cancel(
+    ctx: Context<'_>,
+    #[description = "The job number, as shown by /jobs list"] id: i64,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let jobs = ctx.data().scheduler.guild_jobs(guild_id).await?;
+
+    // Jobs belonging to a feature are turned off through that feature, or they'd come back on restart
+    if let Some(job) = jobs.iter().find(|job| job.id == id && job.name.is_some()) {
+        ctx.send(CreateReply::default().content(format!(
+            "Job #{} is part of {}; turn it off with its own command instead.",
+            id,
+            job.kind.describe().to_lowercase()
+        )))
+        .await?;
+        return Ok(());
+    }
 
-            // Parse the CSV file
-            let mut rdr = csv::Reader::from_reader(file);
-            let mut birthdays: HashMap<String, NaiveDate> = HashMap::new();
-            rdr.records().filter_map(Result::ok).for_each(|record| {
-                if let (Some(username), Some(birth_date)) = (record.get(0), record.get(1)) {
-                    if let Ok(date) = NaiveDate::parse_from_str(birth_date, "%Y-%m-%d") {
-                        birthdays.insert(username.to_string(), date);
+    let content = if ctx.data().scheduler.cancel(guild_id, id).await? {
+        format!("Cancelled job #{}.", id)
+    } else {
+        format!("There's no job #{} in this server.", id)
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum Repeat {
+    Once,
+    Daily,
+    Weekly,
+    #[name = "Custom (cron)"]
+    Cron,
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands("announcement_schedule", "announcement_list", "announcement_cancel"),
+    subcommand_required
+)]
+pub async fn announcement(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+// The schedule for an announcement and when it first goes out,
+// or a message explaining what's wrong with the input
+fn announcement_schedule_for(
+    repeat: Repeat,
+    at: Option<&str>,
+    cron: Option<&str>,
+    tz: &Tz,
+) -> Result<(Schedule, DateTime<Utc>), String> {
+    let now = Utc::now();
+    let today = now.with_timezone(tz).date_naive();
+    let unreadable = |at: &str| {
+        format!(
+            "I couldn't read `{}` as a time. Try something like `20:00` or `friday 20:00`.",
+            at
+        )
+    };
+
+    let schedule = match (repeat, at, cron) {
+        (Repeat::Once, Some(at), _) => {
+            let due = timeparse::resolve_date_time(at, now, tz).ok_or_else(|| unreadable(at))?;
+            if due <= now {
+                return Err("That time has already passed.".to_owned());
+            }
+            return Ok((Schedule::Once, due.with_timezone(&Utc)));
+        }
+        (Repeat::Daily, Some(at), _) => {
+            let (_, time) = timeparse::parse_date_time(at, today).ok_or_else(|| unreadable(at))?;
+            format!("{} {} * * *", time.minute(), time.hour())
+        }
+        (Repeat::Weekly, Some(at), _) => {
+            let (date, time) =
+                timeparse::parse_date_time(at, today).ok_or_else(|| unreadable(at))?;
+            let date = date.ok_or("Say which day it repeats on, e.g. `friday 20:00`.")?;
+            format!(
+                "{} {} * * {}",
+                time.minute(),
+                time.hour(),
+                date.weekday().num_days_from_sunday()
+            )
+        }
+        (Repeat::Cron, _, Some(cron)) => cron.trim().to_owned(),
+        (Repeat::Cron, _, None) => {
+            return Err(
+                "A custom schedule needs a `cron` expression, e.g. `0 20 * * FRI`.".to_owned(),
+            )
+        }
+        (_, None, _) => return Err("Say when to post it with `at`.".to_owned()),
+    };
+
+    let schedule = Schedule::Cron {
+        expression: schedule,
+        timezone: tz.name().to_owned(),
+    };
+    schedule.validate()?;
+    let first_run = schedule
+        .next_run(now, now)
+        .ok()
+        .flatten()
+        .ok_or("That schedule never runs.")?;
+    Ok((schedule, first_run))
+}
+
+#[poise::command(slash_command, guild_only, rename = "schedule", ephemeral)]
+pub async fn announcement_schedule(
+    ctx: Context<'_>,
+    #[description = "What to post"]
+    #[max_length = 2000]
+    text: String,
+    #[description = "When, in your timezone, e.g. \"20:00\", \"friday 20:00\" or \"14/03 18:00\""]
+    at: Option<String>,
+    #[description = "How often to post it (default once)"] repeat: Option<Repeat>,
+    #[description = "For a custom schedule: a cron expression in your timezone, e.g. \"0 20 * * FRI\""]
+    cron: Option<String>,
+    #[description = "Where to post it (default this channel)"]
+    #[channel_types("Text", "News")]
+    channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+    let repeat = repeat.unwrap_or(Repeat::Once);
+
+    // Times are read in the admin's own timezone
+    let user_tz = misc::get_user_timezone(&ctx.data().db, ctx.author().id).await?;
+    let tz = user_tz.unwrap_or(Tz::UTC);
+
+    let (schedule, first_run) =
+        match announcement_schedule_for(repeat, at.as_deref(), cron.as_deref(), &tz) {
+            Ok(scheduled) => scheduled,
+            Err(problem) => {
+                ctx.send(CreateReply::default().content(problem)).await?;
+                return Ok(());
+            }
+        };
+    let description = schedule.describe();
+    // A late weekly ping is more confusing than a missed one; a one-off still goes out
+    let catch_up = match schedule {
+        Schedule::Once => CatchUp::RunOnce,
+        _ => CatchUp::Skip,
+    };
+
+    let id = ctx
+        .data()
+        .scheduler
+        .add(NewJob {
+            name: None,
+            guild_id: Some(guild_id),
+            created_by: Some(ctx.author().id),
+            kind: JobKind::Announcement { channel_id, text },
+            schedule,
+            catch_up,
+            first_run: Some(first_run),
+        })
+        .await?
+        .ok_or("Announcement wasn't saved")?;
+
+    let mut content = format!(
+        "Scheduled announcement #{} in <#{}>, {}. First post {} ({}).",
+        id,
+        channel_id,
+        description,
+        utils::discord_timestamp(first_run.timestamp(), 'F'),
+        utils::discord_timestamp(first_run.timestamp(), 'R')
+    );
+    if user_tz.is_none() {
+        content.push_str(
+            "\nYou haven't set your timezone, so I used UTC. Use `/timezone set` to fix that.",
+        );
+    }
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "list", ephemeral)]
+pub async fn announcement_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let announcements: Vec<Job> = ctx
+        .data()
+        .scheduler
+        .guild_jobs(guild_id)
+        .await?
+        .into_iter()
+        .filter(|job| matches!(job.kind, JobKind::Announcement { .. }))
+        .collect();
+    if announcements.is_empty() {
+        ctx.send(
+            CreateReply::default()
+                .content("No announcements are scheduled. Add one with `/announcement schedule`."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    send_job_list(ctx, "Scheduled announcements", &announcements).await
+}
+
+#[poise::command(slash_command, guild_only, rename = "cancel", ephemeral)]
+pub async fn announcement_cancel(
+    ctx: Context<'_>,
+    #[description = "The announcement number, as shown by /announcement list"] id: i64,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let scheduler = &ctx.data().scheduler;
+    let is_announcement = scheduler
+        .guild_jobs(guild_id)
+        .await?
+        .iter()
+        .any(|job| job.id == id && matches!(job.kind, JobKind::Announcement { .. }));
+
+    let content = if is_announcement && scheduler.cancel(guild_id, id).await? {
+        format!("Cancelled announcement #{}.", id)
+    } else {
+        format!("There's no announcement #{} in this server.", id)
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_link_rule(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+    let partial = partial.to_lowercase();
+    linkfix::guild_rules(&ctx.data().db, guild_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|rule| rule.name)
+        .filter(|name| name.contains(&partial))
+        .take(25)
+        .collect()
+}
+
+fn describe_link_rule(rule: &LinkRule) -> String {
+    let source = match (rule.source, rule.enabled) {
+        (_, false) => " (disabled)",
+        (RuleSource::BuiltIn, true) => "",
+        (RuleSource::Overridden, true) => " (changed)",
+        (RuleSource::Custom, true) => " (custom)",
+    };
+    format!(
+        "**{}**{}: {} `{}` → `{}`",
+        rule.name,
+        source,
+        rule.domains.join(", "),
+        rule.paths.join(" "),
+        rule.replacement
+    )
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    check = "audit::check",
+    subcommands(
+        "link_rules_set",
+        "link_rules_list",
+        "link_rules_disable",
+        "link_rules_enable",
+        "link_rules_reset"
+    ),
+    subcommand_required
+)]
+pub async fn link_rules(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+// Add a rule, or change one. Whatever isn't given is kept as it was.
+#[poise::command(slash_command, guild_only, rename = "set", ephemeral)]
+pub async fn link_rules_set(
+    ctx: Context<'_>,
+    #[description = "The rule's name, e.g. twitter to change the built-in one"]
+    #[autocomplete = "autocomplete_link_rule"]
+    #[max_length = 32]
+    name: String,
+    #[description = "Where to send matched links, e.g. https://vxtwitter.com{path}"]
+    #[max_length = 200]
+    replacement: Option<String>,
+    #[description = "Space-separated domains to match, e.g. \"x.com twitter.com\""]
+    #[max_length = 500]
+    domains: Option<String>,
+    #[description = "Space-separated paths to match, * matching a segment, e.g. \"/*/status/*\""]
+    #[max_length = 500]
+    paths: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = name.trim().to_lowercase();
+    let split = |text: Option<String>| {
+        text.map(|text| {
+            text.split_whitespace()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+        })
+    };
+    // Anything not given stays as the guild last set it, including whether it's enabled
+    let db = &ctx.data().db;
+    let mut overrides = db.get_link_rule_overrides(guild_id).await?;
+    let existing = overrides
+        .iter()
+        .position(|rule| rule.name == name)
+        .map(|index| overrides.remove(index));
+    let change = LinkRuleOverride {
+        name: name.clone(),
+        domains: split(domains).or_else(|| existing.as_ref()?.domains.clone()),
+        // Paths are case-sensitive
+        paths: paths
+            .map(|paths| paths.split_whitespace().map(str::to_owned).collect())
+            .or_else(|| existing.as_ref()?.paths.clone()),
+        replacement: replacement
+            .map(|replacement| replacement.trim().to_owned())
+            .or_else(|| existing.as_ref()?.replacement.clone()),
+        enabled: existing.as_ref().is_none_or(|rule| rule.enabled),
+    };
+
+    // What the rule would be with the change applied
+    overrides.push(change.clone());
+    let rule = linkfix::merge_rules(overrides)
+        .into_iter()
+        .find(|rule| rule.name == name);
+    let Some(rule) = rule else {
+        ctx.send(CreateReply::default().content(format!(
+            "`{}` isn't a built-in rule, so give a replacement, domains and paths for it.",
+            name
+        )))
+        .await?;
+        return Ok(());
+    };
+    if let Err(problem) = rule.validate() {
+        ctx.send(CreateReply::default().content(problem)).await?;
+        return Ok(());
+    }
+
+    db.set_link_rule_override(guild_id, change).await?;
+    ctx.send(CreateReply::default().content(format!("Saved. {}", describe_link_rule(&rule))))
+        .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "list", ephemeral)]
+pub async fn link_rules_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let rules = linkfix::guild_rules(&ctx.data().db, guild_id).await?;
+    let mode = ctx.data().db.get_link_fixing(guild_id).await?;
+
+    // Split over pages well under the embed limits, for guilds with many rules of their own
+    let mut pages: Vec<String> = vec![String::new()];
+    for line in rules.iter().map(describe_link_rule) {
+        let page = pages.last_mut().expect("pages is never empty");
+        if !page.is_empty() && page.chars().count() + line.chars().count() > 3000 {
+            pages.push(line);
+        } else {
+            if !page.is_empty() {
+                page.push('\n');
+            }
+            page.push_str(&line);
+        }
+    }
+
+    let page_count = pages.len();
+    let embeds = pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, page)| {
+            let mut footer = format!(
+                "Fixing links in chat: {}. Change it with /settings link_fixing.",
+                mode.as_str()
+            );
+            if page_count > 1 {
+                footer.push_str(&format!(" Page {}/{}", index + 1, page_count));
+            }
+            CreateEmbed::default()
+                .title("🔗 Link fixing rules")
+                .description(page)
+                .footer(CreateEmbedFooter::new(footer))
+        })
+        .collect();
+    utils::paginate_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+// Whether `name` is one of the guild's rules, telling the admin if not
+async fn check_link_rule_exists(ctx: Context<'_>, name: &str) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let rules = linkfix::guild_rules(&ctx.data().db, guild_id).await?;
+    if rules.iter().any(|rule| rule.name == name) {
+        return Ok(true);
+    }
+    ctx.send(CreateReply::default().content(format!(
+        "There's no rule called `{}`. See `/link_rules list`.",
+        name
+    )))
+    .await?;
+    Ok(false)
+}
+
+#[poise::command(slash_command, guild_only, rename = "disable", ephemeral)]
+pub async fn link_rules_disable(
+    ctx: Context<'_>,
+    #[description = "The rule to stop using"]
+    #[autocomplete = "autocomplete_link_rule"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = name.trim().to_lowercase();
+    if !check_link_rule_exists(ctx, &name).await? {
+        return Ok(());
+    }
+    ctx.data()
+        .db
+        .set_link_rule_enabled(guild_id, &name, false)
+        .await?;
+
+    ctx.send(CreateReply::default().content(format!(
+        "Links matching `{}` are no longer fixed. Turn it back on with `/link_rules enable`.",
+        name
+    )))
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "enable", ephemeral)]
+pub async fn link_rules_enable(
+    ctx: Context<'_>,
+    #[description = "The rule to use again"]
+    #[autocomplete = "autocomplete_link_rule"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = name.trim().to_lowercase();
+    if !check_link_rule_exists(ctx, &name).await? {
+        return Ok(());
+    }
+    ctx.data()
+        .db
+        .set_link_rule_enabled(guild_id, &name, true)
+        .await?;
+
+    ctx.send(CreateReply::default().content(format!("Links matching `{}` are fixed again.", name)))
+        .await?;
+
+    Ok(())
+}
+
+// Undo a guild's changes to a built-in rule, or delete one of its own
+#[poise::command(slash_command, guild_only, rename = "reset", ephemeral)]
+pub async fn link_rules_reset(
+    ctx: Context<'_>,
+    #[description = "The rule to reset to the built-in one, or delete if it's custom"]
+    #[autocomplete = "autocomplete_link_rule"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = name.trim().to_lowercase();
+    let removed = ctx
+        .data()
+        .db
+        .remove_link_rule_override(guild_id, &name)
+        .await?;
+    let builtin = linkfix::builtin_rules()
+        .iter()
+        .any(|rule| rule.name == name);
+
+    let content = match (removed, builtin) {
+        (true, true) => format!("`{}` is back to the built-in rule.", name),
+        (true, false) => format!("Deleted the rule `{}`.", name),
+        (false, true) => format!("`{}` is already the built-in rule.", name),
+        (false, false) => format!("There's no rule called `{}`. See `/link_rules list`.", name),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum RequiredPermission {
+    #[name = "Manage Server"]
+    ManageGuild,
+    #[name = "Manage Channels"]
+    ManageChannels,
+    #[name = "Manage Roles"]
+    ManageRoles,
+    #[name = "Manage Messages"]
+    ManageMessages,
+    #[name = "Moderate Members"]
+    ModerateMembers,
+    #[name = "Mention Everyone"]
+    MentionEveryone,
+    Administrator,
+}
+
+impl RequiredPermission {
+    fn permissions(self) -> Permissions {
+        match self {
+            RequiredPermission::ManageGuild => Permissions::MANAGE_GUILD,
+            RequiredPermission::ManageChannels => Permissions::MANAGE_CHANNELS,
+            RequiredPermission::ManageRoles => Permissions::MANAGE_ROLES,
+            RequiredPermission::ManageMessages => Permissions::MANAGE_MESSAGES,
+            RequiredPermission::ModerateMembers => Permissions::MODERATE_MEMBERS,
+            RequiredPermission::MentionEveryone => Permissions::MENTION_EVERYONE,
+            RequiredPermission::Administrator => Permissions::ADMINISTRATOR,
+        }
+    }
+}
+
+fn describe_permissions(permissions: Permissions) -> String {
+    permissions.get_permission_names().join(", ")
+}
+
+// Who may use the admin commands is configurable, but not who may configure it
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("permissions_set", "permissions_reset", "permissions_list"),
+    subcommand_required
+)]
+pub async fn permissions(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "set",
+    ephemeral
+)]
+pub async fn permissions_set(
+    ctx: Context<'_>,
+    #[description = "The command to change"] command: AdminCommand,
+    #[description = "Members with this role may use it"] role: Option<Role>,
+    #[description = "Members with this permission may use it"] permission: Option<
+        RequiredPermission,
+    >,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    if role.is_none() && permission.is_none() {
+        ctx.send(CreateReply::default().content(
+            "Give a role, a permission or both. Use `/permissions reset` to go back to the default.",
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let permissions = permission.map(RequiredPermission::permissions);
+    ctx.data()
+        .db
+        .set_command_permission(
+            guild_id,
+            CommandPermission {
+                command: command.name().to_owned(),
+                role_id: role.as_ref().map(|role| role.id),
+                permissions,
+            },
+        )
+        .await?;
+    // `/permissions` and `/audit` aren't `AdminCommand`s, so they record themselves
+    audit::record(ctx, &ctx.invocation_string(), None).await?;
+
+    let who = match (&role, permissions) {
+        (Some(role), Some(permissions)) => format!(
+            "Members with <@&{}> or {}",
+            role.id,
+            describe_permissions(permissions)
+        ),
+        (Some(role), None) => format!("Members with <@&{}>", role.id),
+        (None, Some(permissions)) => {
+            format!("Members with {}", describe_permissions(permissions))
+        }
+        (None, None) => unreachable!(),
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "{} can now use `/{}`. Administrators always can.",
+                who,
+                command.name()
+            ))
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "reset",
+    ephemeral
+)]
+pub async fn permissions_reset(
+    ctx: Context<'_>,
+    #[description = "The command to reset"] command: AdminCommand,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data()
+        .db
+        .remove_command_permission(guild_id, command.name())
+        .await?;
+    audit::record(ctx, &ctx.invocation_string(), None).await?;
+
+    ctx.send(CreateReply::default().content(format!(
+        "`/{}` is back to needing {}.",
+        command.name(),
+        describe_permissions(command.default_permissions())
+    )))
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "list",
+    ephemeral
+)]
+pub async fn permissions_list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let rules = ctx.data().db.get_command_permissions(guild_id).await?;
+
+    let lines: Vec<String> = AdminCommand::ALL
+        .into_iter()
+        .map(|command| {
+            let rule = rules.iter().find(|rule| rule.command == command.name());
+            let who = match rule {
+                Some(rule) => {
+                    let mut who: Vec<String> = Vec::new();
+                    if let Some(role_id) = rule.role_id {
+                        who.push(format!("<@&{}>", role_id));
                     }
-                }
-            });
-
-            // Get today's date
-            let today = chrono::Utc::now().date_naive();
-
-            // Check for birthdays
-            for (username, birth_date) in birthdays.iter() {
-                if birth_date.month() == today.month() && birth_date.day() == today.day() {
-                    // Send a birthday message
-                    if let Err(why) = channel_id
-                        .say(&http, format!("🎉 Happy Birthday, {}! 🎂", username))
-                        .await
-                    {
-                        println!("Error sending birthday message: {:?}", why);
+                    if let Some(permissions) = rule.permissions {
+                        who.push(describe_permissions(permissions));
                     }
+                    who.join(" or ")
                 }
-            }
-            // Sleep for 24 hours
-            sleep(Duration::from_secs(24 * 60 * 60)).await;
-        }
-    });
+                None => format!(
+                    "{} (default)",
+                    describe_permissions(command.default_permissions())
+                ),
+            };
+            format!("`/{}`: {}", command.name(), who)
+        })
+        .collect();
 
     ctx.send(
         CreateReply::default()
-            .content("Started birthday reminders!")
-            .ephemeral(true),
+            .embed(
+                CreateEmbed::default()
+                    .title("🔒 Command permissions")
+                    .description(lines.join("\n"))
+                    .footer(CreateEmbedFooter::new(
+                        "Administrators can use every command.",
+                    )),
+            )
+            .allowed_mentions(CreateAllowedMentions::new()),
     )
     .await?;
 
     Ok(())
 }
 
-//#[poise::command(slash_command, guild_only, required_permissions = "ADMINISTRATOR")]
-#[poise::command(slash_command, guild_only)]
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("audit_log", "audit_channel"),
+    subcommand_required
+)]
+pub async fn audit(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "log",
+    ephemeral
+)]
+pub async fn audit_log(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let entries = ctx.data().db.get_audit_entries(guild_id, 100).await?;
+    if entries.is_empty() {
+        ctx.send(CreateReply::default().content("No admin commands have been used yet."))
+            .await?;
+        return Ok(());
+    }
+
+    // One entry to a page, as they look in the mod-log channel
+    let page_count = entries.len();
+    let embeds = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            audit::audit_embed(entry).footer(CreateEmbedFooter::new(format!(
+                "Audit #{} - Page {}/{}",
+                entry.id,
+                index + 1,
+                page_count
+            )))
+        })
+        .collect();
+
+    utils::paginate_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    rename = "channel",
+    ephemeral
+)]
+pub async fn audit_channel(
+    ctx: Context<'_>,
+    #[description = "Where to mirror the audit log (leave empty to turn off)"]
+    #[channel_types("Text")]
+    channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data()
+        .db
+        .set_mod_log_channel(guild_id, channel.as_ref().map(|channel| channel.id))
+        .await?;
+    audit::record(ctx, &ctx.invocation_string(), None).await?;
+
+    let content = match channel {
+        Some(channel) => format!(
+            "Uses of admin commands will be posted in <#{}> as well as kept in `/audit log`.",
+            channel.id
+        ),
+        None => "Uses of admin commands are now only kept in `/audit log`.".to_owned(),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, check = "audit::check")]
 pub async fn say(
     ctx: Context<'_>,
     #[description = "What to say"] text_to_say: String,
@@ -78,7 +1342,8 @@ pub async fn say(
     .await?;
 
     let Some(message_id) = message_id else {
-        ctx.channel_id().say(ctx.http(), text_to_say).await?;
+        let sent = ctx.channel_id().say(ctx.http(), &text_to_say).await?;
+        audit::record(ctx, &text_to_say, Some(sent.id)).await?;
         return Ok(());
     };
 
@@ -89,7 +1354,13 @@ pub async fn say(
             return Ok(());
         }
     };
-    message.reply_ping(ctx.http(), text_to_say).await?;
+    let sent = message.reply_ping(ctx.http(), &text_to_say).await?;
+    let content = format!(
+        "In reply to {}:\n{}",
+        message.id.link(message.channel_id, ctx.guild_id()),
+        text_to_say
+    );
+    audit::record(ctx, &content, Some(sent.id)).await?;
 
     Ok(())
 }
@@ -0,0 +1,184 @@
+use crate::db::AuditEntry;
+use crate::{Context, Error};
+
+use chrono::Utc;
+use poise::CreateReply;
+use serenity::all::{CreateEmbed, CreateEmbedFooter, CreateMessage, MessageId, Permissions};
+
+// Admin commands whose permission a guild can change with `/permissions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum AdminCommand {
+    #[name = "say"]
+    Say,
+    #[name = "announcement"]
+    Announcement,
+    #[name = "jobs"]
+    Jobs,
+    #[name = "settings"]
+    Settings,
+    #[name = "birthday_reminders"]
+    BirthdayReminders,
+    #[name = "birthday_messages"]
+    BirthdayMessages,
+    #[name = "link_rules"]
+    LinkRules,
+}
+
+impl AdminCommand {
+    pub const ALL: [AdminCommand; 7] = [
+        AdminCommand::Say,
+        AdminCommand::Announcement,
+        AdminCommand::Jobs,
+        AdminCommand::Settings,
+        AdminCommand::BirthdayReminders,
+        AdminCommand::BirthdayMessages,
+        AdminCommand::LinkRules,
+    ];
+
+    // Matches the top-level command's name
+    pub fn name(self) -> &'static str {
+        match self {
+            AdminCommand::Say => "say",
+            AdminCommand::Announcement => "announcement",
+            AdminCommand::Jobs => "jobs",
+            AdminCommand::Settings => "settings",
+            AdminCommand::BirthdayReminders => "birthday_reminders",
+            AdminCommand::BirthdayMessages => "birthday_messages",
+            AdminCommand::LinkRules => "link_rules",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AdminCommand> {
+        AdminCommand::ALL
+            .into_iter()
+            .find(|command| command.name() == name)
+    }
+
+    // Who may use the command until the guild says otherwise
+    pub fn default_permissions(self) -> Permissions {
+        match self {
+            // Anything the bot says looks like it came from the server itself
+            AdminCommand::Say => Permissions::ADMINISTRATOR,
+            _ => Permissions::MANAGE_GUILD,
+        }
+    }
+}
+
+// The admin command being run, if it is one. Subcommands count as their group.
+fn admin_command(ctx: Context<'_>) -> Option<AdminCommand> {
+    let root = ctx
+        .parent_commands()
+        .first()
+        .copied()
+        .unwrap_or(ctx.command());
+    AdminCommand::from_name(&root.name)
+}
+
+// Command check for the admin commands, in place of `required_permissions`.
+// Uses the guild's own rule for the command if it has one, else the default permission.
+pub async fn check(ctx: Context<'_>) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let command = admin_command(ctx).ok_or("Not an admin command")?;
+    let Some(member) = ctx.author_member().await else {
+        return Ok(false);
+    };
+    // Only known for slash commands; anyone else needs the role
+    let permissions = member.permissions.unwrap_or_default();
+
+    // Administrators can't lock themselves out
+    let allowed = permissions.administrator()
+        || match ctx
+            .data()
+            .db
+            .get_command_permission(guild_id, command.name())
+            .await?
+        {
+            Some(rule) => {
+                rule.role_id
+                    .is_some_and(|role_id| member.roles.contains(&role_id))
+                    || rule
+                        .permissions
+                        .is_some_and(|required| permissions.contains(required))
+            }
+            None => permissions.contains(command.default_permissions()),
+        };
+
+    if !allowed {
+        ctx.send(
+            CreateReply::default()
+                .content(format!(
+                    "You don't have permission to use `/{}` in this server.",
+                    command.name()
+                ))
+                .ephemeral(true),
+        )
+        .await?;
+    }
+    Ok(allowed)
+}
+
+// Write a use of an admin command to the audit log, and mirror it to the mod-log channel.
+// `message_id` is the message the command posted or replied to.
+pub async fn record(
+    ctx: Context<'_>,
+    content: &str,
+    message_id: Option<MessageId>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let db = &ctx.data().db;
+    let mut entry = AuditEntry {
+        id: 0,
+        guild_id,
+        user_id: ctx.author().id,
+        command: ctx.command().qualified_name.clone(),
+        channel_id: ctx.channel_id(),
+        content: content.to_owned(),
+        message_id,
+        created_at: Utc::now(),
+    };
+    entry.id = db.add_audit_entry(entry.clone()).await?;
+
+    let Some(mod_log) = db.get_mod_log_channel(guild_id).await? else {
+        return Ok(());
+    };
+    let embed = audit_embed(&entry);
+    // The command itself went through, so a broken mod-log only gets logged
+    if let Err(err) = mod_log
+        .send_message(ctx.http(), CreateMessage::new().embed(embed))
+        .await
+    {
+        println!("Error posting to mod-log channel {}: {:?}", mod_log, err);
+    }
+    Ok(())
+}
+
+// Record every successful use of an admin command. Run after each command.
+pub async fn record_command(ctx: Context<'_>) -> Result<(), Error> {
+    match admin_command(ctx) {
+        // `say` records its own use, with the message it sent
+        None | Some(AdminCommand::Say) => Ok(()),
+        Some(_) => record(ctx, &ctx.invocation_string(), None).await,
+    }
+}
+
+pub fn audit_embed(entry: &AuditEntry) -> CreateEmbed {
+    let mut description = format!(
+        "<@{}> used `/{}` in <#{}>",
+        entry.user_id, entry.command, entry.channel_id
+    );
+    if let Some(message_id) = entry.message_id {
+        description.push_str(&format!(
+            " ([message]({}))",
+            message_id.link(entry.channel_id, Some(entry.guild_id))
+        ));
+    }
+    // Embed descriptions are capped at 4096 characters
+    let content: String = entry.content.chars().take(3500).collect();
+    description.push_str(&format!("\n>>> {}", content));
+
+    CreateEmbed::default()
+        .title("📋 Admin command used")
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!("Audit #{}", entry.id)))
+        .timestamp(entry.created_at)
+}
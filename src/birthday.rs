@@ -0,0 +1,838 @@
+use crate::db::{Birthday, Database};
+use crate::misc;
+use crate::scheduler::{CatchUp, JobKind, NewJob, Schedule, Scheduler};
+use crate::timeparse;
+use crate::utils;
+use crate::{Context, Error};
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use poise::CreateReply;
+use rand::seq::IndexedRandom;
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateEmbed, CreateEmbedFooter, CreateMessage, GuildId, Http,
+    Member, RoleId, UserId,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum Month {
+    January = 1,
+    February,
+    March,
+    April,
+    May,
+    June,
+    July,
+    August,
+    September,
+    October,
+    November,
+    December,
+}
+
+// The date a birthday falls on in a given year.
+// Feb 29 birthdays are celebrated on Feb 28 in non-leap years.
+pub fn birthday_in_year(month: u32, day: u32, year: i32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day).or_else(|| {
+        if month == 2 && day == 29 {
+            NaiveDate::from_ymd_opt(year, 2, 28)
+        } else {
+            None
+        }
+    })
+}
+
+// The first birthday on or after `from`
+pub fn next_birthday(month: u32, day: u32, from: NaiveDate) -> Option<NaiveDate> {
+    match birthday_in_year(month, day, from.year()) {
+        Some(date) if date >= from => Some(date),
+        _ => birthday_in_year(month, day, from.year() + 1),
+    }
+}
+
+// How late a greeting may still go out, e.g. after the bot was down
+const DELIVERY_GRACE_HOURS: i64 = 24;
+
+// When the greeting for a year's birthday is due: `hour` o'clock local time
+pub fn greeting_due(month: u32, day: u32, year: i32, hour: u32, tz: &Tz) -> Option<DateTime<Utc>> {
+    let date = birthday_in_year(month, day, year)?;
+    let time = NaiveTime::from_hms_opt(hour, 0, 0)?;
+    timeparse::localize(date.and_time(time), tz).map(|due| due.with_timezone(&Utc))
+}
+
+// Built-in greetings, used until a guild adds its own
+const DEFAULT_GREETINGS: &[&str] = &[
+    "🎉 Happy Birthday, {mention}! 🎂",
+    "🎂 It's {mention}'s birthday today! Have a great one! 🎈",
+    "🎈 Everyone wish {mention} a happy birthday! 🎉",
+    "🥳 {mention} turns {age} today!{milestone}",
+];
+
+// Join anniversaries get their own wording; `{age}` is the number of years
+const ANNIVERSARY_GREETINGS: &[&str] = &[
+    "🎊 Happy {ordinal_age} server anniversary, {mention}!",
+    "🥂 Today marks {mention}'s {ordinal_age} year in the server. Thanks for sticking around!",
+];
+
+// Placeholders a greeting may use; the age ones need the member's birth year and
+// their say-so
+pub const PLACEHOLDERS: &[&str] = &[
+    "{mention}",
+    "{name}",
+    "{age}",
+    "{ordinal_age}",
+    "{milestone}",
+];
+const AGE_PLACEHOLDERS: &[&str] = &["{age}", "{ordinal_age}", "{milestone}"];
+
+// What a greeting is filled in with
+pub struct GreetingFields {
+    pub mention: String,
+    pub name: String,
+    pub age: Option<i32>,
+}
+
+fn is_milestone(age: i32) -> bool {
+    age == 18 || age == 21 || age % 10 == 0
+}
+
+fn ordinal(number: i32) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+fn uses_age(template: &str) -> bool {
+    AGE_PLACEHOLDERS
+        .iter()
+        .any(|placeholder| template.contains(placeholder))
+}
+
+// Check a template only uses known placeholders
+pub fn check_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("The message can't be empty.".to_owned());
+    }
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or("There's a `{` without a closing `}`.")?;
+        let placeholder = &rest[start..start + end + 1];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder `{}`. You can use {}.",
+                placeholder,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+pub fn render_greeting(template: &str, fields: &GreetingFields) -> String {
+    let mut greeting = template
+        .replace("{mention}", &fields.mention)
+        .replace("{name}", &fields.name);
+    if let Some(age) = fields.age {
+        let milestone = if is_milestone(age) {
+            " That's a big one! 🎊"
+        } else {
+            ""
+        };
+        greeting = greeting
+            .replace("{age}", &age.to_string())
+            .replace("{ordinal_age}", &ordinal(age))
+            .replace("{milestone}", milestone);
+    }
+    greeting
+}
+
+// Pick a random greeting from the pool, skipping ones that need an age we don't know
+pub fn pick_greeting(templates: &[String], fields: &GreetingFields) -> String {
+    let usable: Vec<&str> = templates
+        .iter()
+        .map(String::as_str)
+        .filter(|template| fields.age.is_some() || !uses_age(template))
+        .collect();
+    let template = usable
+        .choose(&mut rand::rng())
+        .copied()
+        .unwrap_or(DEFAULT_GREETINGS[0]);
+    render_greeting(template, fields)
+}
+
+// A guild's greetings, or the built-in ones if it has none
+pub async fn greeting_pool(db: &Database, guild_id: GuildId) -> Result<Vec<String>, Error> {
+    let templates: Vec<String> = db
+        .get_birthday_templates(guild_id)
+        .await?
+        .into_iter()
+        .map(|(_, template)| template)
+        .collect();
+    if templates.is_empty() {
+        return Ok(DEFAULT_GREETINGS
+            .iter()
+            .map(|template| (*template).to_owned())
+            .collect());
+    }
+    Ok(templates)
+}
+
+// How a guild wants its birthdays greeted
+struct GuildGreetings {
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    hour: u32,
+    role_id: Option<RoleId>,
+    templates: Vec<String>,
+    // Also send the greeting to the member privately
+    dm: bool,
+}
+
+// Someone whose birthday is greeted
+struct Celebrant {
+    // Identifies them in the delivery log
+    key: String,
+    // Members get the birthday role; legacy names can't
+    user_id: Option<UserId>,
+    name: String,
+    // The year the age counts from: when they were born, or joined the server
+    from_year: Option<i32>,
+    // Whether greetings may mention the age; members opt in for their birthdays
+    share_age: bool,
+    month: u32,
+    day: u32,
+    tz: Tz,
+}
+
+impl Celebrant {
+    fn fields(&self, year: i32) -> GreetingFields {
+        GreetingFields {
+            mention: match self.user_id {
+                Some(user_id) => format!("<@{}>", user_id),
+                None => self.name.clone(),
+            },
+            name: self.name.clone(),
+            age: self
+                .from_year
+                .filter(|_| self.share_age)
+                .map(|from_year| year - from_year)
+                .filter(|age| *age > 0),
+        }
+    }
+}
+
+// Give a member the birthday role until a day after their greeting was due.
+// The removal is saved first, so it still happens if the bot restarts in between.
+async fn grant_birthday_role(
+    http: &Http,
+    db: &Database,
+    guild_id: GuildId,
+    user_id: UserId,
+    role_id: RoleId,
+    due: DateTime<Utc>,
+) -> Result<(), Error> {
+    db.add_birthday_role_grant(guild_id, user_id, role_id, due + ChronoDuration::hours(24))
+        .await?;
+    http.add_member_role(guild_id, user_id, role_id, Some("Birthday"))
+        .await?;
+    Ok(())
+}
+
+// Take back birthday roles whose day is over by `until`
+async fn revoke_birthday_roles(
+    http: &Http,
+    db: &Database,
+    guild_id: GuildId,
+    until: DateTime<Utc>,
+) -> Result<(), Error> {
+    for (user_id, role_id, remove_at) in db.get_birthday_role_grants(guild_id).await? {
+        if remove_at > until {
+            continue;
+        }
+
+        let removed = http
+            .remove_member_role(guild_id, user_id, role_id, Some("Birthday is over"))
+            .await;
+        // Discord rejecting the request means the member or role is gone, so retrying won't help
+        let gone = matches!(&removed, Err(serenity::Error::Http(err))
+            if err.status_code().is_some_and(|status| status.is_client_error()));
+        if let Err(err) = &removed {
+            println!("Error removing birthday role from {}: {:?}", user_id, err);
+        }
+        if removed.is_ok() || gone {
+            db.remove_birthday_role_grant(guild_id, user_id, role_id)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Take back every birthday role still handed out, for when the reminders that would
+// otherwise do so are stopped
+pub async fn revoke_all_birthday_roles(
+    http: &Http,
+    db: &Database,
+    guild_id: GuildId,
+) -> Result<(), Error> {
+    revoke_birthday_roles(http, db, guild_id, DateTime::<Utc>::MAX_UTC).await
+}
+
+// Sends the greeting for one celebrant if it is due and hasn't gone out yet
+async fn deliver_if_due(
+    http: &Http,
+    db: &Database,
+    guild: &GuildGreetings,
+    celebrant: &Celebrant,
+) -> Result<(), Error> {
+    let now = Utc::now();
+
+    // The local year can differ from the UTC one around New Year. Only the
+    // latest birthday that has come around counts, so go newest first.
+    for year in (now.year() - 1..=now.year() + 1).rev() {
+        // Nothing to celebrate in the year they were born or joined
+        if celebrant
+            .from_year
+            .is_some_and(|from_year| year <= from_year)
+        {
+            continue;
+        }
+        let Some(due) = greeting_due(
+            celebrant.month,
+            celebrant.day,
+            year,
+            guild.hour,
+            &celebrant.tz,
+        ) else {
+            continue;
+        };
+        if due > now {
+            continue;
+        }
+        if db
+            .birthday_delivered(guild.guild_id, &celebrant.key, year)
+            .await?
+        {
+            break;
+        }
+        // Too late to greet, e.g. the bot was down or the birthday was only
+        // just added. Marking it delivered means it is only logged once.
+        if now - due > ChronoDuration::hours(DELIVERY_GRACE_HOURS) {
+            println!(
+                "Skipping birthday greeting for {} in {}, due {}",
+                celebrant.key, guild.guild_id, due
+            );
+            db.mark_birthday_delivered(guild.guild_id, &celebrant.key, year)
+                .await?;
+            break;
+        }
+
+        let greeting = pick_greeting(&guild.templates, &celebrant.fields(year));
+        guild.channel_id.say(http, &greeting).await?;
+        db.mark_birthday_delivered(guild.guild_id, &celebrant.key, year)
+            .await?;
+
+        if let Some(user_id) = celebrant.user_id {
+            // Members may have DMs closed, which shouldn't count as a failed greeting
+            if guild.dm {
+                let dm = CreateMessage::new().content(&greeting);
+                if let Err(err) = user_id.direct_message(http, dm).await {
+                    println!("Error sending birthday DM to {}: {:?}", user_id, err);
+                }
+            }
+            if let Some(role_id) = guild.role_id {
+                grant_birthday_role(http, db, guild.guild_id, user_id, role_id, due).await?;
+            }
+        }
+        break;
+    }
+
+    Ok(())
+}
+
+// Greets every birthday that is due in a guild
+async fn deliver_due_birthdays(
+    http: &Http,
+    db: &Database,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    hour: u32,
+) -> Result<(), Error> {
+    let guild = GuildGreetings {
+        guild_id,
+        channel_id,
+        hour,
+        role_id: db.get_birthday_role(guild_id).await?,
+        templates: greeting_pool(db, guild_id).await?,
+        dm: db.get_birthday_dm(guild_id).await?,
+    };
+    // Failing to list them fails the run, rather than passing everyone over
+    let members = utils::guild_members(http, guild_id).await?;
+    let mut celebrants = Vec::new();
+
+    for birthday in db.get_birthdays().await? {
+        // Only greet members of this guild
+        let Some(member) = members.get(&birthday.user_id) else {
+            continue;
+        };
+        let tz = misc::get_user_timezone(db, birthday.user_id)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(Tz::UTC);
+
+        celebrants.push(Celebrant {
+            key: format!("user:{}", birthday.user_id),
+            user_id: Some(birthday.user_id),
+            name: member.display_name().to_owned(),
+            from_year: birthday.year,
+            share_age: birthday.share_age,
+            month: birthday.month,
+            day: birthday.day,
+            tz,
+        });
+    }
+
+    // Birthdays from the old CSV only have a name to go by, and no timezone
+    for (name, birth_date) in db.get_legacy_birthdays(guild_id).await? {
+        celebrants.push(Celebrant {
+            key: format!("name:{}", name),
+            user_id: None,
+            name,
+            from_year: None,
+            share_age: false,
+            month: birth_date.month(),
+            day: birth_date.day(),
+            tz: Tz::UTC,
+        });
+    }
+
+    for celebrant in &celebrants {
+        // One failed greeting shouldn't hold up the others
+        if let Err(err) = deliver_if_due(http, db, &guild, celebrant).await {
+            println!("Error greeting {}: {:?}", celebrant.key, err);
+        }
+    }
+
+    if db.get_anniversaries_enabled(guild_id).await? {
+        deliver_due_anniversaries(http, db, &guild, &members).await?;
+    }
+
+    Ok(())
+}
+
+// Announces the server anniversaries of members who opted in, on the same schedule as birthdays
+async fn deliver_due_anniversaries(
+    http: &Http,
+    db: &Database,
+    birthdays: &GuildGreetings,
+    members: &HashMap<UserId, Member>,
+) -> Result<(), Error> {
+    let guild = GuildGreetings {
+        guild_id: birthdays.guild_id,
+        channel_id: birthdays.channel_id,
+        hour: birthdays.hour,
+        role_id: None,
+        templates: ANNIVERSARY_GREETINGS
+            .iter()
+            .map(|template| (*template).to_owned())
+            .collect(),
+        dm: false,
+    };
+
+    for user_id in db.get_anniversary_optins(guild.guild_id).await? {
+        let Some(member) = members.get(&user_id) else {
+            continue;
+        };
+        let Some(joined_at) = member
+            .joined_at
+            .and_then(|joined_at| DateTime::from_timestamp(joined_at.unix_timestamp(), 0))
+        else {
+            continue;
+        };
+        let tz = misc::get_user_timezone(db, user_id)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(Tz::UTC);
+        // The day they joined where they live
+        let joined = joined_at.with_timezone(&tz).date_naive();
+
+        let celebrant = Celebrant {
+            key: format!("anniversary:{}", user_id),
+            user_id: Some(user_id),
+            name: member.display_name().to_owned(),
+            from_year: Some(joined.year()),
+            share_age: true,
+            month: joined.month(),
+            day: joined.day(),
+            tz,
+        };
+        if let Err(err) = deliver_if_due(http, db, &guild, &celebrant).await {
+            println!("Error announcing {}: {:?}", celebrant.key, err);
+        }
+    }
+
+    Ok(())
+}
+
+// Every 15 minutes lands on the hour in every timezone, however far its offset is from UTC
+const REMINDER_CRON: &str = "*/15 * * * *";
+
+fn reminder_job_name(guild_id: GuildId) -> String {
+    format!("birthdays:{}", guild_id)
+}
+
+// Greet due birthdays and take back birthday roles whose day is over.
+// Run by the scheduler while a guild's reminders are on.
+pub async fn run_reminders(http: &Http, db: &Database, guild_id: GuildId) -> Result<(), Error> {
+    let Some(config) = db.get_birthday_config(guild_id).await? else {
+        return Ok(());
+    };
+    if config.enabled {
+        deliver_due_birthdays(http, db, guild_id, config.channel_id, config.hour).await?;
+    }
+    revoke_birthday_roles(http, db, guild_id, Utc::now()).await
+}
+
+// Schedule a guild's reminders, unless they are already scheduled
+pub async fn start_reminders(scheduler: &Scheduler, guild_id: GuildId) -> Result<(), Error> {
+    scheduler
+        .add(NewJob {
+            name: Some(reminder_job_name(guild_id)),
+            guild_id: Some(guild_id),
+            created_by: None,
+            kind: JobKind::BirthdayReminders,
+            schedule: Schedule::Cron {
+                expression: REMINDER_CRON.to_owned(),
+                timezone: Tz::UTC.name().to_owned(),
+            },
+            // Greetings still go out within a day of a restart; later ones are
+            // logged and dropped, see DELIVERY_GRACE_HOURS
+            catch_up: CatchUp::RunOnce,
+            first_run: None,
+        })
+        .await?;
+    Ok(())
+}
+
+// Returns whether they were scheduled
+pub async fn stop_reminders(scheduler: &Scheduler, guild_id: GuildId) -> Result<bool, Error> {
+    scheduler.cancel_named(&reminder_job_name(guild_id)).await
+}
+
+// When the reminders next run, if they are scheduled
+pub async fn next_reminder_run(
+    scheduler: &Scheduler,
+    guild_id: GuildId,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    Ok(scheduler
+        .named_job(&reminder_job_name(guild_id))
+        .await?
+        .map(|job| job.next_run))
+}
+
+// Whether the author may manage other members' birthdays
+async fn can_manage_others(ctx: Context<'_>) -> bool {
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    member
+        .permissions
+        .is_some_and(|permissions| permissions.manage_guild())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "birthday_set",
+        "birthday_remove",
+        "birthday_list",
+        "birthday_upcoming"
+    ),
+    subcommand_required
+)]
+pub async fn birthday(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "set", ephemeral)]
+pub async fn birthday_set(
+    ctx: Context<'_>,
+    #[description = "The month you were born"] month: Month,
+    #[description = "The day of the month"]
+    #[min = 1]
+    #[max = 31]
+    day: u32,
+    #[description = "The year you were born (optional, only used to show your age if you share it)"]
+    #[min = 1900]
+    year: Option<i32>,
+    #[description = "Mention your age in birthday greetings (default no)"] share_age: Option<bool>,
+    #[description = "Set someone else's birthday (admins only)"] user: Option<Member>,
+) -> Result<(), Error> {
+    let user_id = match &user {
+        Some(member) if member.user.id != ctx.author().id => {
+            if !can_manage_others(ctx).await {
+                ctx.send(CreateReply::default().content(
+                    "You need the Manage Server permission to set other members' birthdays.",
+                ))
+                .await?;
+                return Ok(());
+            }
+            member.user.id
+        }
+        _ => ctx.author().id,
+    };
+
+    let month = month as u32;
+    // 2000 was a leap year, so Feb 29 is accepted
+    let valid_day = NaiveDate::from_ymd_opt(year.unwrap_or(2000), month, day).is_some();
+    let in_future = year.is_some_and(|year| year > Utc::now().year());
+    if !valid_day || in_future {
+        ctx.send(CreateReply::default().content("That isn't a valid date."))
+            .await?;
+        return Ok(());
+    }
+
+    // Only members can choose to share their own age; otherwise it stays as it was
+    let db = &ctx.data().db;
+    let share_age = match share_age {
+        Some(share_age) if user_id == ctx.author().id => share_age,
+        _ => db
+            .get_birthday(user_id)
+            .await?
+            .is_some_and(|birthday| birthday.share_age),
+    };
+    db.set_birthday(Birthday {
+        user_id,
+        month,
+        day,
+        year,
+        share_age,
+    })
+    .await?;
+
+    let date = NaiveDate::from_ymd_opt(2000, month, day).ok_or("Invalid date")?;
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Saved <@{}>'s birthday as {}.",
+                user_id,
+                date.format("%-d %B")
+            ))
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "remove", ephemeral)]
+pub async fn birthday_remove(
+    ctx: Context<'_>,
+    #[description = "Remove someone else's birthday (admins only)"] user: Option<Member>,
+) -> Result<(), Error> {
+    let user_id = match &user {
+        Some(member) if member.user.id != ctx.author().id => {
+            if !can_manage_others(ctx).await {
+                ctx.send(CreateReply::default().content(
+                    "You need the Manage Server permission to remove other members' birthdays.",
+                ))
+                .await?;
+                return Ok(());
+            }
+            member.user.id
+        }
+        _ => ctx.author().id,
+    };
+
+    let content = if ctx.data().db.remove_birthday(user_id).await? {
+        format!("Removed <@{}>'s birthday.", user_id)
+    } else {
+        format!("<@{}> doesn't have a birthday saved.", user_id)
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// Birthdays of members of the current guild, in calendar order
+async fn guild_birthdays(ctx: Context<'_>) -> Result<Vec<(Member, Birthday)>, Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let mut members = utils::guild_members(ctx, guild_id).await?;
+    let mut birthdays = Vec::new();
+    for birthday in ctx.data().db.get_birthdays().await? {
+        // Anyone else isn't in this guild
+        if let Some(member) = members.remove(&birthday.user_id) {
+            birthdays.push((member, birthday));
+        }
+    }
+    Ok(birthdays)
+}
+
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn birthday_list(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let birthdays = guild_birthdays(ctx).await?;
+    if birthdays.is_empty() {
+        ctx.send(CreateReply::default().content(
+            "Nobody in this server has saved their birthday yet. Use `/birthday set` to add yours.",
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = birthdays
+        .iter()
+        .filter_map(|(member, birthday)| {
+            let date = NaiveDate::from_ymd_opt(2000, birthday.month, birthday.day)?;
+            Some(format!(
+                "{} - {}",
+                date.format("%-d %B"),
+                member.display_name()
+            ))
+        })
+        .collect();
+
+    let pages: Vec<String> = lines.chunks(20).map(|chunk| chunk.join("\n")).collect();
+    let page_count = pages.len();
+    let embeds = pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, page)| {
+            CreateEmbed::default()
+                .title("🎂 Birthdays")
+                .description(page)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Page {}/{}",
+                    index + 1,
+                    page_count
+                )))
+        })
+        .collect();
+
+    utils::paginate_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "upcoming")]
+pub async fn birthday_upcoming(
+    ctx: Context<'_>,
+    #[description = "How many birthdays to show (default 5)"]
+    #[min = 1]
+    #[max = 25]
+    count: Option<usize>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let today = Utc::now().date_naive();
+    let mut upcoming = Vec::new();
+    for (member, birthday) in guild_birthdays(ctx).await? {
+        let Some(date) = next_birthday(birthday.month, birthday.day, today) else {
+            continue;
+        };
+        // The start of the day where they live, or UTC if we don't know
+        let tz = misc::get_user_timezone(&ctx.data().db, member.user.id)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(Tz::UTC);
+        let Some(starts) = timeparse::localize(date.and_time(NaiveTime::MIN), &tz) else {
+            continue;
+        };
+        upcoming.push((starts.timestamp(), member));
+    }
+    upcoming.sort_by_key(|(starts, _)| *starts);
+
+    if upcoming.is_empty() {
+        ctx.send(CreateReply::default().content("No upcoming birthdays."))
+            .await?;
+        return Ok(());
+    }
+
+    let mut description = String::new();
+    for (starts, member) in upcoming.iter().take(count.unwrap_or(5)) {
+        description.push_str(&format!(
+            "{} ({}) - <@{}>\n",
+            utils::discord_timestamp(*starts, 'D'),
+            utils::discord_timestamp(*starts, 'R'),
+            member.user.id
+        ));
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("🎂 Upcoming birthdays")
+                    .description(description),
+            )
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("anniversary_opt_in", "anniversary_opt_out"),
+    subcommand_required
+)]
+pub async fn anniversary(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "opt_in", ephemeral)]
+pub async fn anniversary_opt_in(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.data()
+        .db
+        .set_anniversary_optin(guild_id, ctx.author().id, true)
+        .await?;
+
+    let mut content =
+        "The anniversary of when you joined this server will be announced each year.".to_owned();
+    if !ctx.data().db.get_anniversaries_enabled(guild_id).await? {
+        content.push_str(" Anniversaries are currently turned off here, so nothing is announced until an admin turns them on.");
+    }
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "opt_out", ephemeral)]
+pub async fn anniversary_opt_out(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let content = if ctx
+        .data()
+        .db
+        .set_anniversary_optin(guild_id, ctx.author().id, false)
+        .await?
+    {
+        "Your server anniversary will no longer be announced."
+    } else {
+        "Your server anniversary wasn't being announced."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
@@ -0,0 +1,1607 @@
+use crate::linkfix::LinkFixMode;
+use crate::scheduler::{CatchUp, Job};
+use crate::Error;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::all::{ChannelId, GuildId, MessageId, Permissions, RoleId, UserId};
+use std::fs::File;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Each entry is applied once, in order, and recorded in `PRAGMA user_version`.
+// Never edit a migration that has shipped; append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, mirroring the old CSV files
+    "CREATE TABLE timezones (
+        user_id INTEGER PRIMARY KEY,
+        timezone TEXT NOT NULL
+    );
+    CREATE TABLE birthdays (
+        name TEXT PRIMARY KEY,
+        birth_date TEXT NOT NULL
+    );
+    CREATE TABLE legacy_imports (
+        file_name TEXT PRIMARY KEY,
+        imported_at TEXT NOT NULL
+    );",
+    // 2: per-guild settings
+    "CREATE TABLE guild_settings (
+        guild_id INTEGER PRIMARY KEY,
+        time_detection INTEGER NOT NULL DEFAULT 0
+    );",
+    // 3: daylight saving announcements
+    "ALTER TABLE guild_settings ADD COLUMN dst_channel_id INTEGER;
+    ALTER TABLE guild_settings ADD COLUMN dst_notice_days INTEGER NOT NULL DEFAULT 3;
+    CREATE TABLE dst_announcements (
+        guild_id INTEGER NOT NULL,
+        timezone TEXT NOT NULL,
+        transition_at INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, timezone, transition_at)
+    );",
+    // 4: birthdays keyed by user ID; the old free-text names are kept aside
+    "ALTER TABLE birthdays RENAME TO legacy_birthdays;
+    CREATE TABLE birthdays (
+        user_id INTEGER PRIMARY KEY,
+        month INTEGER NOT NULL,
+        day INTEGER NOT NULL,
+        year INTEGER
+    );",
+    // 5: birthday greetings already sent, so restarts never repeat or skip one.
+    // `celebrant` is "user:<id>", or "name:<name>" for legacy birthdays.
+    "CREATE TABLE birthday_deliveries (
+        guild_id INTEGER NOT NULL,
+        celebrant TEXT NOT NULL,
+        year INTEGER NOT NULL,
+        delivered_at TEXT NOT NULL,
+        PRIMARY KEY (guild_id, celebrant, year)
+    );",
+    // 6: birthday reminder configuration, resumed on startup
+    "ALTER TABLE guild_settings ADD COLUMN birthday_channel_id INTEGER;
+    ALTER TABLE guild_settings ADD COLUMN birthday_enabled INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE guild_settings ADD COLUMN birthday_hour INTEGER NOT NULL DEFAULT 0;",
+    // 7: birthday role, and when to take it back from each member
+    "ALTER TABLE guild_settings ADD COLUMN birthday_role_id INTEGER;
+    CREATE TABLE birthday_role_grants (
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        role_id INTEGER NOT NULL,
+        remove_at INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, user_id, role_id)
+    );",
+    // 8: custom birthday greetings
+    "ALTER TABLE guild_settings ADD COLUMN birthday_dm INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE birthday_templates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        template TEXT NOT NULL
+    );",
+    // 9: server join anniversaries, opted into by the guild and each member
+    "ALTER TABLE guild_settings ADD COLUMN anniversaries INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE anniversary_optins (
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, user_id)
+    );",
+    // 10: scheduled jobs; `kind` and `schedule` hold JSON
+    "CREATE TABLE jobs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT UNIQUE,
+        guild_id INTEGER,
+        created_by INTEGER,
+        kind TEXT NOT NULL,
+        schedule TEXT NOT NULL,
+        catch_up TEXT NOT NULL,
+        next_run INTEGER NOT NULL
+    );
+    CREATE INDEX jobs_next_run ON jobs (next_run);",
+    // 11: personal reminders, delivered by the scheduler
+    "CREATE TABLE reminders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        message_id INTEGER,
+        text TEXT NOT NULL,
+        dm INTEGER NOT NULL,
+        due_at INTEGER NOT NULL,
+        delivered_at INTEGER
+    );",
+    // 12: per-guild permissions for admin commands, and the audit log of their use
+    "ALTER TABLE guild_settings ADD COLUMN mod_log_channel_id INTEGER;
+    CREATE TABLE command_permissions (
+        guild_id INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        role_id INTEGER,
+        permissions INTEGER,
+        PRIMARY KEY (guild_id, command)
+    );
+    CREATE TABLE audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        channel_id INTEGER NOT NULL,
+        content TEXT NOT NULL,
+        message_id INTEGER,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX audit_log_guild ON audit_log (guild_id, created_at);",
+    // 13: fixing embeds of links posted in chat
+    "ALTER TABLE guild_settings ADD COLUMN link_fixing TEXT NOT NULL DEFAULT 'off';
+    CREATE TABLE link_fix_optouts (
+        user_id INTEGER PRIMARY KEY
+    );",
+    // 14: guilds' changes to the link fixing rules; NULL keeps the built-in rule's value
+    "CREATE TABLE link_rules (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        domains TEXT,
+        paths TEXT,
+        replacement TEXT,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        PRIMARY KEY (guild_id, name)
+    );",
+    // 15: channels where book and film links get cards
+    "CREATE TABLE link_preview_channels (
+        channel_id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL
+    );",
+    // 16: whether members want their age in birthday greetings
+    "ALTER TABLE birthdays ADD COLUMN share_age INTEGER NOT NULL DEFAULT 0;",
+    // 17: which server the old CSV birthdays belong to
+    "ALTER TABLE legacy_birthdays ADD COLUMN guild_id INTEGER;",
+];
+
+pub struct Birthday {
+    pub user_id: UserId,
+    pub month: u32,
+    pub day: u32,
+    // Only used to work out ages, which are only shown if `share_age`
+    pub year: Option<i32>,
+    pub share_age: bool,
+}
+
+pub struct BirthdayConfig {
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub enabled: bool,
+    // Local hour in each member's timezone to send greetings
+    pub hour: u32,
+}
+
+pub struct Reminder {
+    pub id: i64,
+    pub user_id: UserId,
+    // Where it was set, and the confirmation message to reply to
+    pub channel_id: ChannelId,
+    pub message_id: Option<MessageId>,
+    pub text: String,
+    pub dm: bool,
+    pub due_at: DateTime<Utc>,
+}
+
+// Who may use one of the admin commands in a guild, replacing its default permission.
+// Members need the role or the permissions, whichever is set.
+pub struct CommandPermission {
+    pub command: String,
+    pub role_id: Option<RoleId>,
+    pub permissions: Option<Permissions>,
+}
+
+#[derive(Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub command: String,
+    pub channel_id: ChannelId,
+    pub content: String,
+    // The message the command posted or replied to, if any
+    pub message_id: Option<MessageId>,
+    pub created_at: DateTime<Utc>,
+}
+
+// A guild's change to a built-in link fixing rule, or a rule of its own.
+// Fields left as None keep the built-in rule's.
+#[derive(Clone)]
+pub struct LinkRuleOverride {
+    pub name: String,
+    pub domains: Option<Vec<String>>,
+    pub paths: Option<Vec<String>>,
+    pub replacement: Option<String>,
+    pub enabled: bool,
+}
+
+// Shared handle to the bot's SQLite database.
+// Cloning is cheap; all clones use the same connection.
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // Run a closure against the connection on the blocking thread pool,
+    // so SQLite I/O never stalls the async runtime.
+    pub async fn call<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&mut conn)
+        })
+        .await??;
+        Ok(result)
+    }
+
+    pub async fn get_timezone(&self, user_id: UserId) -> Result<Option<String>, Error> {
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT timezone FROM timezones WHERE user_id = ?1",
+                params![user_id.get() as i64],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    pub async fn set_timezone(&self, user_id: UserId, timezone: &str) -> Result<(), Error> {
+        let timezone = timezone.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO timezones (user_id, timezone) VALUES (?1, ?2)
+                 ON CONFLICT (user_id) DO UPDATE SET timezone = excluded.timezone",
+                params![user_id.get() as i64, timezone],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether the user had a timezone to clear.
+    pub async fn clear_timezone(&self, user_id: UserId) -> Result<bool, Error> {
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM timezones WHERE user_id = ?1",
+                    params![user_id.get() as i64],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_timezones(&self) -> Result<Vec<(UserId, String)>, Error> {
+        self.call(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT user_id, timezone FROM timezones ORDER BY user_id")?;
+            let rows = stmt.query_map([], |row| Ok((id_column(row, 0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn get_birthday(&self, user_id: UserId) -> Result<Option<Birthday>, Error> {
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT user_id, month, day, year, share_age FROM birthdays WHERE user_id = ?1",
+                params![user_id.get() as i64],
+                birthday_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    pub async fn get_birthdays(&self) -> Result<Vec<Birthday>, Error> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT user_id, month, day, year, share_age FROM birthdays ORDER BY month, day",
+            )?;
+            let rows = stmt.query_map([], birthday_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn set_birthday(&self, birthday: Birthday) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO birthdays (user_id, month, day, year, share_age)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (user_id) DO UPDATE SET
+                    month = excluded.month, day = excluded.day, year = excluded.year,
+                    share_age = excluded.share_age",
+                params![
+                    birthday.user_id.get() as i64,
+                    birthday.month,
+                    birthday.day,
+                    birthday.year,
+                    birthday.share_age
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether there was a birthday to remove
+    pub async fn remove_birthday(&self, user_id: UserId) -> Result<bool, Error> {
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM birthdays WHERE user_id = ?1",
+                    params![user_id.get() as i64],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_birthday_config(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Option<BirthdayConfig>, Error> {
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT guild_id, birthday_channel_id, birthday_enabled, birthday_hour
+                 FROM guild_settings WHERE guild_id = ?1 AND birthday_channel_id IS NOT NULL",
+                params![guild_id.get() as i64],
+                birthday_config_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    pub async fn get_enabled_birthday_configs(&self) -> Result<Vec<BirthdayConfig>, Error> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT guild_id, birthday_channel_id, birthday_enabled, birthday_hour
+                 FROM guild_settings WHERE birthday_channel_id IS NOT NULL AND birthday_enabled = 1",
+            )?;
+            let rows = stmt.query_map([], birthday_config_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn set_birthday_config(&self, config: BirthdayConfig) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, birthday_channel_id, birthday_enabled, birthday_hour)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (guild_id) DO UPDATE SET
+                    birthday_channel_id = excluded.birthday_channel_id,
+                    birthday_enabled = excluded.birthday_enabled,
+                    birthday_hour = excluded.birthday_hour",
+                params![
+                    config.guild_id.get() as i64,
+                    config.channel_id.get() as i64,
+                    config.enabled,
+                    config.hour
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether reminders were running
+    pub async fn disable_birthday_reminders(&self, guild_id: GuildId) -> Result<bool, Error> {
+        let updated = self
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE guild_settings SET birthday_enabled = 0
+                     WHERE guild_id = ?1 AND birthday_enabled = 1",
+                    params![guild_id.get() as i64],
+                )
+            })
+            .await?;
+        Ok(updated > 0)
+    }
+
+    pub async fn get_birthday_role(&self, guild_id: GuildId) -> Result<Option<RoleId>, Error> {
+        let role_id = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT birthday_role_id FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| optional_id_column(row, 0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(role_id.flatten())
+    }
+
+    pub async fn set_birthday_role(
+        &self,
+        guild_id: GuildId,
+        role_id: Option<RoleId>,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, birthday_role_id) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET birthday_role_id = excluded.birthday_role_id",
+                params![guild_id.get() as i64, role_id.map(|id| id.get() as i64)],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_birthday_dm(&self, guild_id: GuildId) -> Result<bool, Error> {
+        let enabled = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT birthday_dm FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(enabled.unwrap_or(false))
+    }
+
+    pub async fn set_birthday_dm(&self, guild_id: GuildId, enabled: bool) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, birthday_dm) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET birthday_dm = excluded.birthday_dm",
+                params![guild_id.get() as i64, enabled],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // A guild's own birthday greetings with their IDs, oldest first
+    pub async fn get_birthday_templates(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<(i64, String)>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, template FROM birthday_templates WHERE guild_id = ?1 ORDER BY id",
+            )?;
+            let rows = stmt.query_map(params![guild_id.get() as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    // Returns the new template's ID
+    pub async fn add_birthday_template(
+        &self,
+        guild_id: GuildId,
+        template: &str,
+    ) -> Result<i64, Error> {
+        let template = template.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO birthday_templates (guild_id, template) VALUES (?1, ?2)",
+                params![guild_id.get() as i64, template],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    // Returns whether there was such a template
+    pub async fn remove_birthday_template(
+        &self,
+        guild_id: GuildId,
+        id: i64,
+    ) -> Result<bool, Error> {
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM birthday_templates WHERE guild_id = ?1 AND id = ?2",
+                    params![guild_id.get() as i64, id],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_anniversaries_enabled(&self, guild_id: GuildId) -> Result<bool, Error> {
+        let enabled = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT anniversaries FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(enabled.unwrap_or(false))
+    }
+
+    pub async fn set_anniversaries_enabled(
+        &self,
+        guild_id: GuildId,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, anniversaries) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET anniversaries = excluded.anniversaries",
+                params![guild_id.get() as i64, enabled],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Members of a guild who want their join anniversary announced
+    pub async fn get_anniversary_optins(&self, guild_id: GuildId) -> Result<Vec<UserId>, Error> {
+        self.call(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT user_id FROM anniversary_optins WHERE guild_id = ?1")?;
+            let rows = stmt.query_map(params![guild_id.get() as i64], |row| id_column(row, 0))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    // Returns whether anything changed
+    pub async fn set_anniversary_optin(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        opted_in: bool,
+    ) -> Result<bool, Error> {
+        let changed = self
+            .call(move |conn| {
+                let sql = if opted_in {
+                    "INSERT OR IGNORE INTO anniversary_optins (guild_id, user_id) VALUES (?1, ?2)"
+                } else {
+                    "DELETE FROM anniversary_optins WHERE guild_id = ?1 AND user_id = ?2"
+                };
+                conn.execute(sql, params![guild_id.get() as i64, user_id.get() as i64])
+            })
+            .await?;
+        Ok(changed > 0)
+    }
+
+    // Remember to take a birthday role back at `remove_at`
+    pub async fn add_birthday_role_grant(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+        remove_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO birthday_role_grants (guild_id, user_id, role_id, remove_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    guild_id.get() as i64,
+                    user_id.get() as i64,
+                    role_id.get() as i64,
+                    remove_at.timestamp()
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Birthday roles still handed out in a guild, with when to remove them
+    pub async fn get_birthday_role_grants(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<(UserId, RoleId, DateTime<Utc>)>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT user_id, role_id, remove_at FROM birthday_role_grants WHERE guild_id = ?1",
+            )?;
+            let rows = stmt.query_map(params![guild_id.get() as i64], |row| {
+                Ok((
+                    id_column(row, 0)?,
+                    id_column(row, 1)?,
+                    DateTime::from_timestamp(row.get(2)?, 0).unwrap_or_default(),
+                ))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn remove_birthday_role_grant(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM birthday_role_grants
+                 WHERE guild_id = ?1 AND user_id = ?2 AND role_id = ?3",
+                params![
+                    guild_id.get() as i64,
+                    user_id.get() as i64,
+                    role_id.get() as i64
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn birthday_delivered(
+        &self,
+        guild_id: GuildId,
+        celebrant: &str,
+        year: i32,
+    ) -> Result<bool, Error> {
+        let celebrant = celebrant.to_owned();
+        let delivered = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT 1 FROM birthday_deliveries
+                     WHERE guild_id = ?1 AND celebrant = ?2 AND year = ?3",
+                    params![guild_id.get() as i64, celebrant, year],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(delivered.is_some())
+    }
+
+    pub async fn mark_birthday_delivered(
+        &self,
+        guild_id: GuildId,
+        celebrant: &str,
+        year: i32,
+    ) -> Result<(), Error> {
+        let celebrant = celebrant.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO birthday_deliveries (guild_id, celebrant, year, delivered_at)
+                 VALUES (?1, ?2, ?3, datetime('now'))",
+                params![guild_id.get() as i64, celebrant, year],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Birthdays imported from the old CSV, keyed by free-text name. Rows that
+    // were never tied to a server are left out everywhere.
+    pub async fn get_legacy_birthdays(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<(String, NaiveDate)>, Error> {
+        self.call(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT name, birth_date FROM legacy_birthdays WHERE guild_id = ?1")?;
+            let rows = stmt.query_map(params![guild_id.get() as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn get_time_detection(&self, guild_id: GuildId) -> Result<bool, Error> {
+        let enabled = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT time_detection FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(enabled.unwrap_or(false))
+    }
+
+    pub async fn set_time_detection(&self, guild_id: GuildId, enabled: bool) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, time_detection) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET time_detection = excluded.time_detection",
+                params![guild_id.get() as i64, enabled],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // None turns the announcements off
+    pub async fn set_dst_channel(
+        &self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        notice_days: u32,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, dst_channel_id, dst_notice_days) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (guild_id) DO UPDATE SET
+                    dst_channel_id = excluded.dst_channel_id,
+                    dst_notice_days = excluded.dst_notice_days",
+                params![
+                    guild_id.get() as i64,
+                    channel_id.map(|id| id.get() as i64),
+                    notice_days
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Guilds with a DST announcement channel, with how many days ahead to warn
+    pub async fn get_dst_channels(&self) -> Result<Vec<(GuildId, ChannelId, u32)>, Error> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT guild_id, dst_channel_id, dst_notice_days FROM guild_settings
+                 WHERE dst_channel_id IS NOT NULL",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((id_column(row, 0)?, id_column(row, 1)?, row.get(2)?))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn dst_announced(
+        &self,
+        guild_id: GuildId,
+        timezone: &str,
+        transition_at: i64,
+    ) -> Result<bool, Error> {
+        let timezone = timezone.to_owned();
+        let announced = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT 1 FROM dst_announcements
+                     WHERE guild_id = ?1 AND timezone = ?2 AND transition_at = ?3",
+                    params![guild_id.get() as i64, timezone, transition_at],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(announced.is_some())
+    }
+
+    pub async fn mark_dst_announced(
+        &self,
+        guild_id: GuildId,
+        timezone: &str,
+        transition_at: i64,
+    ) -> Result<(), Error> {
+        let timezone = timezone.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO dst_announcements (guild_id, timezone, transition_at)
+                 VALUES (?1, ?2, ?3)",
+                params![guild_id.get() as i64, timezone, transition_at],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns the new job's ID, or None if a job with the same name already exists
+    pub async fn add_job(&self, job: Job) -> Result<Option<i64>, Error> {
+        let kind = serde_json::to_string(&job.kind)?;
+        let schedule = serde_json::to_string(&job.schedule)?;
+        self.call(move |conn| {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO jobs (name, guild_id, created_by, kind, schedule, catch_up, next_run)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    job.name,
+                    job.guild_id.map(|id| id.get() as i64),
+                    job.created_by.map(|id| id.get() as i64),
+                    kind,
+                    schedule,
+                    job.catch_up.as_str(),
+                    job.next_run.timestamp()
+                ],
+            )?;
+            Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+        })
+        .await
+    }
+
+    pub async fn get_named_job(&self, name: &str) -> Result<Option<Job>, Error> {
+        let name = name.to_owned();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT id, guild_id, created_by, kind, schedule, catch_up, next_run, name
+                 FROM jobs WHERE name = ?1",
+                params![name],
+                job_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    // Returns whether there was such a job
+    pub async fn remove_named_job(&self, name: &str) -> Result<bool, Error> {
+        let name = name.to_owned();
+        let deleted = self
+            .call(move |conn| conn.execute("DELETE FROM jobs WHERE name = ?1", params![name]))
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_guild_jobs(&self, guild_id: GuildId) -> Result<Vec<Job>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, guild_id, created_by, kind, schedule, catch_up, next_run, name
+                 FROM jobs WHERE guild_id = ?1 ORDER BY next_run",
+            )?;
+            let rows = stmt.query_map(params![guild_id.get() as i64], job_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    // Jobs that can no longer be read, e.g. after a job kind was removed, are dropped
+    // rather than holding up every other job
+    pub async fn get_due_jobs(&self, now: DateTime<Utc>) -> Result<Vec<Job>, Error> {
+        self.call(move |conn| {
+            let mut jobs = Vec::new();
+            let mut broken = Vec::new();
+            {
+                let mut stmt = conn.prepare(
+                    "SELECT id, guild_id, created_by, kind, schedule, catch_up, next_run, name
+                     FROM jobs WHERE next_run <= ?1 ORDER BY next_run",
+                )?;
+                let mut rows = stmt.query(params![now.timestamp()])?;
+                while let Some(row) = rows.next()? {
+                    match job_from_row(row) {
+                        Ok(job) => jobs.push(job),
+                        Err(
+                            err @ (rusqlite::Error::FromSqlConversionFailure(..)
+                            | rusqlite::Error::InvalidColumnType(..)),
+                        ) => {
+                            let id: i64 = row.get(0)?;
+                            println!("Dropping job {} that can't be read: {}", id, err);
+                            broken.push(id);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            for id in broken {
+                conn.execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+            }
+            Ok(jobs)
+        })
+        .await
+    }
+
+    pub async fn get_next_job_run(&self) -> Result<Option<DateTime<Utc>>, Error> {
+        let next_run: Option<i64> = self
+            .call(|conn| conn.query_row("SELECT MIN(next_run) FROM jobs", [], |row| row.get(0)))
+            .await?;
+        Ok(next_run.and_then(|next_run| DateTime::from_timestamp(next_run, 0)))
+    }
+
+    pub async fn set_job_next_run(&self, id: i64, next_run: DateTime<Utc>) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE jobs SET next_run = ?2 WHERE id = ?1",
+                params![id, next_run.timestamp()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_job(&self, id: i64) -> Result<(), Error> {
+        self.call(move |conn| conn.execute("DELETE FROM jobs WHERE id = ?1", params![id]))
+            .await?;
+        Ok(())
+    }
+
+    // Returns whether the guild had such a job
+    pub async fn remove_guild_job(&self, guild_id: GuildId, id: i64) -> Result<bool, Error> {
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM jobs WHERE guild_id = ?1 AND id = ?2",
+                    params![guild_id.get() as i64, id],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    // Returns the new reminder's ID
+    pub async fn add_reminder(
+        &self,
+        user_id: UserId,
+        channel_id: ChannelId,
+        text: &str,
+        dm: bool,
+        due_at: DateTime<Utc>,
+    ) -> Result<i64, Error> {
+        let text = text.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO reminders (user_id, channel_id, text, dm, due_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    user_id.get() as i64,
+                    channel_id.get() as i64,
+                    text,
+                    dm,
+                    due_at.timestamp()
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    pub async fn set_reminder_message(&self, id: i64, message_id: MessageId) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE reminders SET message_id = ?2 WHERE id = ?1",
+                params![id, message_id.get() as i64],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_reminder(&self, id: i64) -> Result<Option<Reminder>, Error> {
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT id, user_id, channel_id, message_id, text, dm, due_at
+                 FROM reminders WHERE id = ?1",
+                params![id],
+                reminder_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    // A member's reminders that haven't gone off yet, soonest first
+    pub async fn get_pending_reminders(&self, user_id: UserId) -> Result<Vec<Reminder>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, user_id, channel_id, message_id, text, dm, due_at
+                 FROM reminders WHERE user_id = ?1 AND delivered_at IS NULL ORDER BY due_at",
+            )?;
+            let rows = stmt.query_map(params![user_id.get() as i64], reminder_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    // Delivered reminders are kept for a week so they can still be snoozed
+    pub async fn mark_reminder_delivered(&self, id: i64) -> Result<(), Error> {
+        let now = Utc::now().timestamp();
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE reminders SET delivered_at = ?2 WHERE id = ?1",
+                params![id, now],
+            )?;
+            conn.execute(
+                "DELETE FROM reminders WHERE delivered_at < ?1",
+                params![now - 7 * 24 * 60 * 60],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn snooze_reminder(&self, id: i64, due_at: DateTime<Utc>) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE reminders SET due_at = ?2, delivered_at = NULL WHERE id = ?1",
+                params![id, due_at.timestamp()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether there was such a reminder
+    pub async fn remove_reminder(&self, id: i64) -> Result<bool, Error> {
+        let deleted = self
+            .call(move |conn| conn.execute("DELETE FROM reminders WHERE id = ?1", params![id]))
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_command_permission(
+        &self,
+        guild_id: GuildId,
+        command: &str,
+    ) -> Result<Option<CommandPermission>, Error> {
+        let command = command.to_owned();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT command, role_id, permissions FROM command_permissions
+                 WHERE guild_id = ?1 AND command = ?2",
+                params![guild_id.get() as i64, command],
+                command_permission_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    pub async fn get_command_permissions(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<CommandPermission>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT command, role_id, permissions FROM command_permissions
+                 WHERE guild_id = ?1 ORDER BY command",
+            )?;
+            let rows =
+                stmt.query_map(params![guild_id.get() as i64], command_permission_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn set_command_permission(
+        &self,
+        guild_id: GuildId,
+        permission: CommandPermission,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO command_permissions (guild_id, command, role_id, permissions)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (guild_id, command) DO UPDATE SET
+                    role_id = excluded.role_id,
+                    permissions = excluded.permissions",
+                params![
+                    guild_id.get() as i64,
+                    permission.command,
+                    permission.role_id.map(|id| id.get() as i64),
+                    permission.permissions.map(|bits| bits.bits() as i64)
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether the command had its own permission
+    pub async fn remove_command_permission(
+        &self,
+        guild_id: GuildId,
+        command: &str,
+    ) -> Result<bool, Error> {
+        let command = command.to_owned();
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM command_permissions WHERE guild_id = ?1 AND command = ?2",
+                    params![guild_id.get() as i64, command],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_mod_log_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>, Error> {
+        let channel_id = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT mod_log_channel_id FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| optional_id_column(row, 0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(channel_id.flatten())
+    }
+
+    pub async fn set_mod_log_channel(
+        &self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, mod_log_channel_id) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET mod_log_channel_id = excluded.mod_log_channel_id",
+                params![guild_id.get() as i64, channel_id.map(|id| id.get() as i64)],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns the new entry's ID
+    pub async fn add_audit_entry(&self, entry: AuditEntry) -> Result<i64, Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO audit_log (guild_id, user_id, command, channel_id, content, message_id, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.guild_id.get() as i64,
+                    entry.user_id.get() as i64,
+                    entry.command,
+                    entry.channel_id.get() as i64,
+                    entry.content,
+                    entry.message_id.map(|id| id.get() as i64),
+                    entry.created_at.timestamp()
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    // A guild's most recent audit entries, newest first
+    pub async fn get_audit_entries(
+        &self,
+        guild_id: GuildId,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, guild_id, user_id, command, channel_id, content, message_id, created_at
+                 FROM audit_log WHERE guild_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            let rows =
+                stmt.query_map(params![guild_id.get() as i64, limit], audit_entry_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn get_link_fixing(&self, guild_id: GuildId) -> Result<LinkFixMode, Error> {
+        let mode = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT link_fixing FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id.get() as i64],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(mode
+            .and_then(|mode| LinkFixMode::parse(&mode))
+            .unwrap_or(LinkFixMode::Off))
+    }
+
+    pub async fn set_link_fixing(&self, guild_id: GuildId, mode: LinkFixMode) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO guild_settings (guild_id, link_fixing) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET link_fixing = excluded.link_fixing",
+                params![guild_id.get() as i64, mode.as_str()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Members who don't want their links fixed, in any guild
+    pub async fn get_link_fix_optout(&self, user_id: UserId) -> Result<bool, Error> {
+        let opted_out = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT 1 FROM link_fix_optouts WHERE user_id = ?1",
+                    params![user_id.get() as i64],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(opted_out.is_some())
+    }
+
+    // Returns whether anything changed
+    pub async fn set_link_fix_optout(
+        &self,
+        user_id: UserId,
+        opted_out: bool,
+    ) -> Result<bool, Error> {
+        let changed = self
+            .call(move |conn| {
+                let sql = if opted_out {
+                    "INSERT OR IGNORE INTO link_fix_optouts (user_id) VALUES (?1)"
+                } else {
+                    "DELETE FROM link_fix_optouts WHERE user_id = ?1"
+                };
+                conn.execute(sql, params![user_id.get() as i64])
+            })
+            .await?;
+        Ok(changed > 0)
+    }
+
+    pub async fn get_link_rule_overrides(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<LinkRuleOverride>, Error> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT name, domains, paths, replacement, enabled FROM link_rules
+                 WHERE guild_id = ?1 ORDER BY rowid",
+            )?;
+            let rows =
+                stmt.query_map(params![guild_id.get() as i64], link_rule_override_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    // Lists are stored space-separated, as domains and paths can't contain spaces
+    pub async fn set_link_rule_override(
+        &self,
+        guild_id: GuildId,
+        rule: LinkRuleOverride,
+    ) -> Result<(), Error> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO link_rules (guild_id, name, domains, paths, replacement, enabled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (guild_id, name) DO UPDATE SET
+                    domains = excluded.domains,
+                    paths = excluded.paths,
+                    replacement = excluded.replacement,
+                    enabled = excluded.enabled",
+                params![
+                    guild_id.get() as i64,
+                    rule.name,
+                    rule.domains.map(|domains| domains.join(" ")),
+                    rule.paths.map(|paths| paths.join(" ")),
+                    rule.replacement,
+                    rule.enabled
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_link_rule_enabled(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let name = name.to_owned();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO link_rules (guild_id, name, enabled) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (guild_id, name) DO UPDATE SET enabled = excluded.enabled",
+                params![guild_id.get() as i64, name, enabled],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Returns whether the guild had changed the rule
+    pub async fn remove_link_rule_override(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+    ) -> Result<bool, Error> {
+        let name = name.to_owned();
+        let deleted = self
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM link_rules WHERE guild_id = ?1 AND name = ?2",
+                    params![guild_id.get() as i64, name],
+                )
+            })
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    pub async fn get_link_previews(&self, channel_id: ChannelId) -> Result<bool, Error> {
+        let enabled = self
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT 1 FROM link_preview_channels WHERE channel_id = ?1",
+                    params![channel_id.get() as i64],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(enabled.is_some())
+    }
+
+    // Returns whether anything changed
+    pub async fn set_link_previews(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        enabled: bool,
+    ) -> Result<bool, Error> {
+        let changed = self
+            .call(move |conn| {
+                if enabled {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO link_preview_channels (channel_id, guild_id) VALUES (?1, ?2)",
+                        params![channel_id.get() as i64, guild_id.get() as i64],
+                    )
+                } else {
+                    conn.execute(
+                        "DELETE FROM link_preview_channels WHERE channel_id = ?1",
+                        params![channel_id.get() as i64],
+                    )
+                }
+            })
+            .await?;
+        Ok(changed > 0)
+    }
+
+    // One-time import of the flat files the bot used before it had a database.
+    // Each file is only imported once; it is left on disk untouched. The old
+    // birthdays were only ever for one server, `legacy_guild`; without it they
+    // are imported but never greeted.
+    pub async fn import_legacy_csv(
+        &self,
+        timezones_path: &str,
+        birthdays_path: &str,
+        legacy_guild: Option<GuildId>,
+    ) -> Result<(), Error> {
+        let timezones_path = timezones_path.to_owned();
+        let birthdays_path = birthdays_path.to_owned();
+
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            if let Some(mut rdr) = open_unimported(&tx, &timezones_path)? {
+                let mut imported = 0;
+                for record in rdr.records().filter_map(Result::ok) {
+                    let (Some(user_id), Some(timezone)) = (record.get(0), record.get(1)) else {
+                        continue;
+                    };
+                    let Some(user_id) = user_id.trim().parse::<u64>().ok().filter(|&id| id != 0)
+                    else {
+                        println!(
+                            "Skipping invalid user ID in {}: {}",
+                            timezones_path, user_id
+                        );
+                        continue;
+                    };
+                    let Ok(timezone) = timezone.trim().parse::<Tz>() else {
+                        println!(
+                            "Skipping invalid timezone in {}: {}",
+                            timezones_path, timezone
+                        );
+                        continue;
+                    };
+                    tx.execute(
+                        "INSERT OR IGNORE INTO timezones (user_id, timezone) VALUES (?1, ?2)",
+                        params![user_id as i64, timezone.name()],
+                    )?;
+                    imported += 1;
+                }
+                mark_imported(&tx, &timezones_path)?;
+                println!("Imported {} timezones from {}", imported, timezones_path);
+            }
+
+            if let Some(mut rdr) = open_unimported(&tx, &birthdays_path)? {
+                let mut imported = 0;
+                for record in rdr.records().filter_map(Result::ok) {
+                    let (Some(name), Some(birth_date)) = (record.get(0), record.get(1)) else {
+                        continue;
+                    };
+                    if NaiveDate::parse_from_str(birth_date.trim(), "%Y-%m-%d").is_err() {
+                        println!(
+                            "Skipping invalid birth date in {}: {}",
+                            birthdays_path, birth_date
+                        );
+                        continue;
+                    }
+                    tx.execute(
+                        "INSERT OR IGNORE INTO legacy_birthdays (name, birth_date) VALUES (?1, ?2)",
+                        params![name.trim(), birth_date.trim()],
+                    )?;
+                    imported += 1;
+                }
+                mark_imported(&tx, &birthdays_path)?;
+                println!("Imported {} birthdays from {}", imported, birthdays_path);
+            }
+
+            // Also claims rows imported before the server could be configured
+            if let Some(guild_id) = legacy_guild {
+                tx.execute(
+                    "UPDATE legacy_birthdays SET guild_id = ?1 WHERE guild_id IS NULL",
+                    params![guild_id.get() as i64],
+                )?;
+            }
+
+            tx.commit()
+        })
+        .await
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+        println!("Applied database migration {}", index + 1);
+    }
+
+    Ok(())
+}
+
+// Discord IDs are never 0, and serenity's ID types panic on one, so a stored 0 is an error
+fn id_column<T: From<NonZeroU64>>(row: &rusqlite::Row, index: usize) -> rusqlite::Result<T> {
+    let id: i64 = row.get(index)?;
+    NonZeroU64::new(id as u64).map(T::from).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Integer,
+            format!("Invalid Discord ID: {}", id).into(),
+        )
+    })
+}
+
+fn optional_id_column<T: From<NonZeroU64>>(
+    row: &rusqlite::Row,
+    index: usize,
+) -> rusqlite::Result<Option<T>> {
+    match row.get::<_, Option<i64>>(index)? {
+        Some(_) => id_column(row, index).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn birthday_from_row(row: &rusqlite::Row) -> rusqlite::Result<Birthday> {
+    Ok(Birthday {
+        user_id: id_column(row, 0)?,
+        month: row.get(1)?,
+        day: row.get(2)?,
+        year: row.get(3)?,
+        share_age: row.get(4)?,
+    })
+}
+
+fn birthday_config_from_row(row: &rusqlite::Row) -> rusqlite::Result<BirthdayConfig> {
+    Ok(BirthdayConfig {
+        guild_id: id_column(row, 0)?,
+        channel_id: id_column(row, 1)?,
+        enabled: row.get(2)?,
+        hour: row.get(3)?,
+    })
+}
+
+fn reminder_from_row(row: &rusqlite::Row) -> rusqlite::Result<Reminder> {
+    Ok(Reminder {
+        id: row.get(0)?,
+        user_id: id_column(row, 1)?,
+        channel_id: id_column(row, 2)?,
+        message_id: optional_id_column(row, 3)?,
+        text: row.get(4)?,
+        dm: row.get(5)?,
+        due_at: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+    })
+}
+
+fn command_permission_from_row(row: &rusqlite::Row) -> rusqlite::Result<CommandPermission> {
+    Ok(CommandPermission {
+        command: row.get(0)?,
+        role_id: optional_id_column(row, 1)?,
+        permissions: row
+            .get::<_, Option<i64>>(2)?
+            .map(|bits| Permissions::from_bits_truncate(bits as u64)),
+    })
+}
+
+fn audit_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<AuditEntry> {
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        guild_id: id_column(row, 1)?,
+        user_id: id_column(row, 2)?,
+        command: row.get(3)?,
+        channel_id: id_column(row, 4)?,
+        content: row.get(5)?,
+        message_id: optional_id_column(row, 6)?,
+        created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_default(),
+    })
+}
+
+fn link_rule_override_from_row(row: &rusqlite::Row) -> rusqlite::Result<LinkRuleOverride> {
+    let split = |text: Option<String>| {
+        text.map(|text| text.split_whitespace().map(str::to_owned).collect())
+    };
+    Ok(LinkRuleOverride {
+        name: row.get(0)?,
+        domains: split(row.get(1)?),
+        paths: split(row.get(2)?),
+        replacement: row.get(3)?,
+        enabled: row.get(4)?,
+    })
+}
+
+fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<Job> {
+    // JSON columns that no longer parse, e.g. after a job kind was removed
+    let from_json = |index: usize, text: String| {
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            format!("Invalid job data: {}", text).into(),
+        )
+    };
+    let kind: String = row.get(3)?;
+    let schedule: String = row.get(4)?;
+    let catch_up: String = row.get(5)?;
+    Ok(Job {
+        id: row.get(0)?,
+        name: row.get(7)?,
+        guild_id: optional_id_column(row, 1)?,
+        created_by: optional_id_column(row, 2)?,
+        kind: serde_json::from_str(&kind).map_err(|_| from_json(3, kind.clone()))?,
+        schedule: serde_json::from_str(&schedule).map_err(|_| from_json(4, schedule.clone()))?,
+        catch_up: CatchUp::parse(&catch_up).ok_or_else(|| from_json(5, catch_up.clone()))?,
+        next_run: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+    })
+}
+
+fn open_unimported(conn: &Connection, path: &str) -> rusqlite::Result<Option<csv::Reader<File>>> {
+    let already_imported = conn
+        .query_row(
+            "SELECT 1 FROM legacy_imports WHERE file_name = ?1",
+            params![path],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if already_imported {
+        return Ok(None);
+    }
+
+    match File::open(path) {
+        Ok(file) => Ok(Some(csv::Reader::from_reader(file))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn mark_imported(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO legacy_imports (file_name, imported_at) VALUES (?1, datetime('now'))",
+        params![path],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use crate::db::Database;
+use crate::misc;
+use crate::utils;
+use crate::Error;
+
+use chrono::{DateTime, Duration, Offset, Utc};
+use chrono_tz::Tz;
+use serenity::all::{CreateAllowedMentions, CreateMessage, GuildId};
+use serenity::client::Context as SerenityContext;
+
+// How far ahead to look for other zones shifting on a different day
+const CONTEXT_DAYS: i64 = 30;
+
+pub struct Transition {
+    pub tz: Tz,
+    pub at: DateTime<Utc>,
+    // UTC offsets in seconds before and after the change
+    pub before: i32,
+    pub after: i32,
+}
+
+fn offset_at(tz: &Tz, moment: DateTime<Utc>) -> i32 {
+    moment.with_timezone(tz).offset().fix().local_minus_utc()
+}
+
+// Offset changes for a zone between `from` and `until`. Steps through the period an
+// hour at a time to spot a change, then minute by minute to pin it down.
+pub fn find_transitions(tz: Tz, from: DateTime<Utc>, until: DateTime<Utc>) -> Vec<Transition> {
+    let mut transitions = Vec::new();
+    let mut moment = from;
+    let mut offset = offset_at(&tz, moment);
+
+    while moment < until {
+        let next = moment + Duration::hours(1);
+        let next_offset = offset_at(&tz, next);
+        if next_offset != offset {
+            let mut at = moment;
+            while offset_at(&tz, at) == offset {
+                at += Duration::minutes(1);
+            }
+            transitions.push(Transition {
+                tz,
+                at,
+                before: offset,
+                after: next_offset,
+            });
+        }
+        moment = next;
+        offset = next_offset;
+    }
+
+    transitions
+}
+
+fn describe_shift(transition: &Transition) -> String {
+    let minutes = (transition.after - transition.before) / 60;
+    let direction = if minutes > 0 { "forward" } else { "back" };
+    let amount = match minutes.abs() {
+        60 => "1 hour".to_owned(),
+        minutes if minutes % 60 == 0 => format!("{} hours", minutes / 60),
+        minutes => format!("{} minutes", minutes),
+    };
+    format!("clocks go {} {}", direction, amount)
+}
+
+// Announce what's coming up in every guild with an announcement channel.
+// Run a few times a day by the scheduler.
+pub async fn announce_dst_transitions(ctx: &SerenityContext, db: &Database) -> Result<(), Error> {
+    for (guild_id, channel_id, notice_days) in db.get_dst_channels().await? {
+        let Some(message) = build_announcement(ctx, db, guild_id, notice_days)
+            .await
+            .unwrap_or_else(|err| {
+                println!("Error checking DST transitions for {}: {:?}", guild_id, err);
+                None
+            })
+        else {
+            continue;
+        };
+        let (content, announced) = message;
+
+        if let Err(why) = channel_id
+            .send_message(
+                ctx,
+                CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await
+        {
+            println!("Error sending DST announcement: {:?}", why);
+            continue;
+        }
+
+        for transition in announced {
+            if let Err(err) = db
+                .mark_dst_announced(guild_id, transition.tz.name(), transition.at.timestamp())
+                .await
+            {
+                println!("Error recording DST announcement: {:?}", err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The heads-up for a guild, or None if nothing new is coming up.
+// Returns the message along with the transitions it covers.
+async fn build_announcement(
+    ctx: &SerenityContext,
+    db: &Database,
+    guild_id: GuildId,
+    notice_days: u32,
+) -> Result<Option<(String, Vec<Transition>)>, Error> {
+    let (members, _) = misc::guild_timezones(ctx, db, guild_id).await?;
+
+    // Member names per zone in use
+    let mut zones: Vec<(Tz, Vec<String>)> = Vec::new();
+    for (member, tz) in members {
+        let name = member.display_name().to_owned();
+        match zones.iter_mut().find(|(zone, _)| *zone == tz) {
+            Some((_, names)) => names.push(name),
+            None => zones.push((tz, vec![name])),
+        }
+    }
+
+    let now = Utc::now();
+    let notice_until = now + Duration::days(i64::from(notice_days));
+    let context_until = now + Duration::days(CONTEXT_DAYS);
+
+    let mut upcoming = Vec::new();
+    let mut later = Vec::new();
+    let mut unchanged = Vec::new();
+    for (tz, _) in &zones {
+        let transitions = find_transitions(*tz, now, context_until);
+        if transitions.is_empty() {
+            unchanged.push(*tz);
+        }
+        for transition in transitions {
+            if transition.at > notice_until {
+                later.push(transition);
+            } else if !db
+                .dst_announced(guild_id, tz.name(), transition.at.timestamp())
+                .await?
+            {
+                upcoming.push(transition);
+            }
+        }
+    }
+
+    if upcoming.is_empty() {
+        return Ok(None);
+    }
+    upcoming.sort_by_key(|transition| transition.at);
+    later.sort_by_key(|transition| transition.at);
+
+    let mut content = String::from("⏰ **Daylight saving time changes are coming up**\n");
+    for transition in &upcoming {
+        let names = zones
+            .iter()
+            .find(|(tz, _)| *tz == transition.tz)
+            .map(|(_, names)| names.join(", "))
+            .unwrap_or_default();
+        let unix = transition.at.timestamp();
+        content.push_str(&format!(
+            "- **{}** ({}): {} on {} ({}), {} → {}\n",
+            transition.tz.name(),
+            names,
+            describe_shift(transition),
+            utils::discord_timestamp(unix, 'F'),
+            utils::discord_timestamp(unix, 'R'),
+            misc::format_offset(transition.before),
+            misc::format_offset(transition.after),
+        ));
+    }
+
+    // Point out when zones shift on different days, since that is when shared
+    // meeting times quietly move by an hour
+    let first = upcoming[0].at;
+    let last = upcoming[upcoming.len() - 1].at;
+    if last.date_naive() != first.date_naive() {
+        content.push_str(&format!(
+            "\nThese zones don't all shift on the same day, so meetings between them move by an hour from {} until {}.\n",
+            utils::discord_timestamp(first.timestamp(), 'D'),
+            utils::discord_timestamp(last.timestamp(), 'D'),
+        ));
+    }
+    if !later.is_empty() {
+        let shifts_later: Vec<String> = later
+            .iter()
+            .map(|transition| {
+                format!(
+                    "{} on {}",
+                    transition.tz.name(),
+                    utils::discord_timestamp(transition.at.timestamp(), 'D')
+                )
+            })
+            .collect();
+        content.push_str(&format!(
+            "\nOther zones here shift later: {}. Until then, meetings with them move by an hour.\n",
+            shifts_later.join(", ")
+        ));
+    }
+    if !unchanged.is_empty() {
+        let names: Vec<&str> = unchanged.iter().map(|tz| tz.name()).collect();
+        content.push_str(&format!(
+            "\n{} {} not change, so meetings with members there move by an hour.\n",
+            names.join(", "),
+            if names.len() == 1 { "does" } else { "do" }
+        ));
+    }
+
+    Ok(Some((content, upcoming)))
+}
@@ -0,0 +1,114 @@
+use chrono_tz::Tz;
+use std::sync::LazyLock;
+
+// Compact offline extract of cities and the timezone each one observes.
+// Columns: name, region, country, timezone
+const CITIES_CSV: &str = include_str!("../assets/cities.csv");
+
+pub struct Place {
+    pub name: String,
+    pub region: String,
+    pub country: String,
+    pub timezone: Tz,
+}
+
+impl Place {
+    pub fn label(&self) -> String {
+        if self.region == self.name || self.region == self.country {
+            format!("{}, {}", self.name, self.country)
+        } else {
+            format!("{}, {}, {}", self.name, self.region, self.country)
+        }
+    }
+}
+
+static PLACES: LazyLock<Vec<Place>> = LazyLock::new(|| {
+    csv::Reader::from_reader(CITIES_CSV.as_bytes())
+        .records()
+        .filter_map(Result::ok)
+        .filter_map(|record| {
+            Some(Place {
+                name: record.get(0)?.to_owned(),
+                region: record.get(1)?.to_owned(),
+                country: record.get(2)?.to_owned(),
+                timezone: record.get(3)?.parse().ok()?,
+            })
+        })
+        .collect()
+});
+
+// Lowercase and drop common diacritics so "malmo" finds "Malmö"
+fn fold(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ä' | 'ã' | 'å' | 'ą' => 'a',
+            'ç' | 'č' | 'ć' => 'c',
+            'é' | 'è' | 'ê' | 'ë' | 'ę' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ñ' | 'ń' => 'n',
+            'ó' | 'ò' | 'ô' | 'ö' | 'õ' | 'ø' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ș' | 'š' | 'ś' => 's',
+            'ž' | 'ź' | 'ż' => 'z',
+            'ł' => 'l',
+            'ț' => 't',
+            other => other,
+        })
+        .collect()
+}
+
+// Resolve a city or country name to candidate places.
+// A city name wins over a country name; a country yields one place per timezone it spans.
+// Falls back to cities starting with the query. At most 25 results, the most a select menu can show.
+pub fn lookup(query: &str) -> Vec<&'static Place> {
+    let query = fold(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let cities: Vec<&Place> = PLACES
+        .iter()
+        .filter(|place| fold(&place.name) == query)
+        .collect();
+    if !cities.is_empty() {
+        return cities.into_iter().take(25).collect();
+    }
+
+    let mut country: Vec<&Place> = Vec::new();
+    for place in PLACES.iter().filter(|place| fold(&place.country) == query) {
+        if !country.iter().any(|seen| seen.timezone == place.timezone) {
+            country.push(place);
+        }
+    }
+    if !country.is_empty() {
+        return country.into_iter().take(25).collect();
+    }
+
+    PLACES
+        .iter()
+        .filter(|place| fold(&place.name).starts_with(&query))
+        .take(25)
+        .collect()
+}
+
+// Place labels containing the partial input, for slash command autocomplete
+pub fn suggest(partial: &str) -> Vec<String> {
+    let partial = fold(partial);
+    PLACES
+        .iter()
+        .filter(|place| {
+            fold(&place.name).contains(&partial) || fold(&place.country).starts_with(&partial)
+        })
+        .map(|place| place.name.clone())
+        .fold(Vec::new(), |mut names, name| {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+            names
+        })
+        .into_iter()
+        .take(25)
+        .collect()
+}
@@ -0,0 +1,582 @@
+use crate::db::{Database, LinkRuleOverride};
+use crate::sanitize;
+use crate::tweets;
+use crate::{Context, Data, Error};
+
+use poise::CreateReply;
+use reqwest::Url;
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditMessage, GuildId, Message, UserId,
+};
+use serenity::client::Context as SerenityContext;
+use std::ops::Range;
+
+// Links fixed in one reply, so a wall of links doesn't become a wall of embeds
+const MAX_LINKS: usize = 5;
+const DELETE_BUTTON_PREFIX: &str = "linkfix-delete-";
+
+// How a guild fixes links posted in chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum LinkFixMode {
+    Off,
+    #[name = "Reply with the fixed links"]
+    Reply,
+    // Needs Manage Messages, to hide the original embeds
+    #[name = "Reply and hide the original embeds"]
+    Replace,
+    // Like Replace, but twitter/x posts get embeds built from the fxtwitter API
+    #[name = "Build embeds for posts and hide the original embeds"]
+    Embed,
+}
+
+impl LinkFixMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkFixMode::Off => "off",
+            LinkFixMode::Reply => "reply",
+            LinkFixMode::Replace => "replace",
+            LinkFixMode::Embed => "embed",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<LinkFixMode> {
+        match text {
+            "off" => Some(LinkFixMode::Off),
+            "reply" => Some(LinkFixMode::Reply),
+            "replace" => Some(LinkFixMode::Replace),
+            "embed" => Some(LinkFixMode::Embed),
+            _ => None,
+        }
+    }
+
+    pub fn hides_original(self) -> bool {
+        matches!(self, LinkFixMode::Replace | LinkFixMode::Embed)
+    }
+}
+
+// A built-in rewrite for a site whose links don't embed well in Discord
+struct BuiltinRule {
+    name: &'static str,
+    domains: &'static [&'static str],
+    paths: &'static [&'static str],
+    replacement: &'static str,
+}
+
+// See `LinkRule` for how these are read
+const BUILTIN_RULES: [BuiltinRule; 9] = [
+    BuiltinRule {
+        name: "twitter",
+        domains: &["x.com", "twitter.com"],
+        paths: &["/*/status/*"],
+        replacement: "https://fxtwitter.com{path}",
+    },
+    BuiltinRule {
+        name: "instagram",
+        domains: &["instagram.com"],
+        paths: &[
+            "/p/*",
+            "/reel/*",
+            "/reels/*",
+            "/tv/*",
+            "/*/p/*",
+            "/*/reel/*",
+        ],
+        replacement: "https://kkinstagram.com{path}",
+    },
+    BuiltinRule {
+        name: "tiktok",
+        domains: &["tiktok.com"],
+        paths: &["/@*/video/*", "/@*/photo/*", "/t/*"],
+        replacement: "https://vxtiktok.com{path}",
+    },
+    // Share links like vm.tiktok.com/ZMabc123
+    BuiltinRule {
+        name: "tiktok_short",
+        domains: &["vm.tiktok.com", "vt.tiktok.com"],
+        paths: &["/*"],
+        replacement: "https://vm.vxtiktok.com{path}",
+    },
+    BuiltinRule {
+        name: "reddit",
+        domains: &["reddit.com"],
+        paths: &[
+            "/r/*/comments/*",
+            "/r/*/s/*",
+            "/u/*/comments/*",
+            "/user/*/comments/*",
+        ],
+        replacement: "https://rxddit.com{path}",
+    },
+    BuiltinRule {
+        name: "bluesky",
+        domains: &["bsky.app"],
+        paths: &["/profile/*/post/*"],
+        replacement: "https://fxbsky.app{path}",
+    },
+    BuiltinRule {
+        name: "threads",
+        domains: &["threads.net", "threads.com"],
+        paths: &["/@*/post/*"],
+        replacement: "https://fixthreads.net{path}",
+    },
+    BuiltinRule {
+        name: "pixiv",
+        domains: &["pixiv.net"],
+        paths: &["/artworks/*", "/*/artworks/*"],
+        replacement: "https://phixiv.net{path}",
+    },
+    // Shorts embed as a still image, ordinary videos play inline
+    BuiltinRule {
+        name: "youtube_shorts",
+        domains: &["youtube.com"],
+        paths: &["/shorts/*"],
+        replacement: "https://www.youtube.com/watch?v={1}",
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleSource {
+    BuiltIn,
+    // A built-in rule the guild has changed
+    Overridden,
+    Custom,
+}
+
+// Rewrites links to a site into links that embed properly.
+// A link matches if its domain is one of `domains` or a subdomain of one, and its path
+// starts like one of `paths`. In paths, `*` matches the rest of a segment, so
+// "/@*/post/*" matches "/@someone/post/123/media". The matched link becomes
+// `replacement`, with `{path}` standing for its path and query and `{1}`, `{2}`... for
+// what the `*`s matched.
+#[derive(Debug, Clone)]
+pub struct LinkRule {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub paths: Vec<String>,
+    pub replacement: String,
+    pub enabled: bool,
+    pub source: RuleSource,
+}
+
+impl LinkRule {
+    // The rewritten link, if the rule applies to `url`
+    pub fn apply(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?;
+        let matches_domain = self.domains.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|subdomain| subdomain.ends_with('.'))
+        });
+        if !matches_domain {
+            return None;
+        }
+
+        let segments: Vec<&str> = url
+            .path_segments()?
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let captures = self
+            .paths
+            .iter()
+            .find_map(|pattern| match_path(pattern, &segments))?;
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        let mut link = self.replacement.replace("{path}", &path);
+        for (index, capture) in captures.iter().enumerate() {
+            link = link.replace(&format!("{{{}}}", index + 1), capture);
+        }
+        Some(link)
+    }
+
+    // Check a rule from an admin makes sense before it is saved
+    pub fn validate(&self) -> Result<(), String> {
+        if self.domains.is_empty() || self.paths.is_empty() {
+            return Err("A rule needs at least one domain and one path.".to_owned());
+        }
+        for domain in &self.domains {
+            let valid = domain.contains('.')
+                && domain
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-');
+            if !valid {
+                return Err(format!(
+                    "`{}` isn't a domain. Give domains like `example.com`, without `https://`.",
+                    domain
+                ));
+            }
+        }
+        if let Some(path) = self.paths.iter().find(|path| !path.starts_with('/')) {
+            return Err(format!(
+                "`{}` isn't a path. Paths start with `/`, like `/*/status/*`.",
+                path
+            ));
+        }
+
+        // Fill in the placeholders to see what's left
+        let mut example = self.replacement.replace("{path}", "/example");
+        for index in 1..=9 {
+            example = example.replace(&format!("{{{}}}", index), "example");
+        }
+        if example.contains(['{', '}']) {
+            return Err(
+                "The replacement can only use `{path}` and `{1}`, `{2}`... as placeholders."
+                    .to_owned(),
+            );
+        }
+        match Url::parse(&example) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+            _ => Err(format!(
+                "`{}` isn't a link. Replacements look like `https://fxtwitter.com{{path}}`.",
+                self.replacement
+            )),
+        }
+    }
+}
+
+// What each `*` in `pattern` matched, if `segments` starts like it
+fn match_path<'a>(pattern: &str, segments: &[&'a str]) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+    if parts.len() > segments.len() {
+        return None;
+    }
+
+    let mut captures = Vec::new();
+    for (part, segment) in parts.iter().zip(segments) {
+        match part.strip_suffix('*') {
+            Some(prefix) => {
+                let rest = segment.strip_prefix(prefix)?;
+                if rest.is_empty() {
+                    return None;
+                }
+                captures.push(rest);
+            }
+            None if part == segment => {}
+            None => return None,
+        }
+    }
+    Some(captures)
+}
+
+pub fn builtin_rules() -> Vec<LinkRule> {
+    BUILTIN_RULES
+        .iter()
+        .map(|rule| LinkRule {
+            name: rule.name.to_owned(),
+            domains: rule
+                .domains
+                .iter()
+                .map(|&domain| domain.to_owned())
+                .collect(),
+            paths: rule.paths.iter().map(|&path| path.to_owned()).collect(),
+            replacement: rule.replacement.to_owned(),
+            enabled: true,
+            source: RuleSource::BuiltIn,
+        })
+        .collect()
+}
+
+// The built-in rules with a guild's changes applied, followed by its own rules.
+// Disabled rules are included, so they can be listed.
+pub fn merge_rules(overrides: Vec<LinkRuleOverride>) -> Vec<LinkRule> {
+    let mut rules = builtin_rules();
+    for change in overrides {
+        if let Some(rule) = rules.iter_mut().find(|rule| rule.name == change.name) {
+            if change.domains.is_some() || change.paths.is_some() || change.replacement.is_some() {
+                rule.source = RuleSource::Overridden;
+            }
+            rule.domains = change.domains.unwrap_or(rule.domains.clone());
+            rule.paths = change.paths.unwrap_or(rule.paths.clone());
+            rule.replacement = change.replacement.unwrap_or(rule.replacement.clone());
+            rule.enabled = change.enabled;
+            continue;
+        }
+
+        let (Some(domains), Some(paths), Some(replacement)) =
+            (change.domains, change.paths, change.replacement)
+        else {
+            continue;
+        };
+        rules.push(LinkRule {
+            name: change.name,
+            domains,
+            paths,
+            replacement,
+            enabled: change.enabled,
+            source: RuleSource::Custom,
+        });
+    }
+    rules
+}
+
+pub async fn guild_rules(db: &Database, guild_id: GuildId) -> Result<Vec<LinkRule>, Error> {
+    Ok(merge_rules(db.get_link_rule_overrides(guild_id).await?))
+}
+
+// The first enabled rule's version of `link`, or None if no rule applies.
+// Redirects are followed and trackers stripped first, see `sanitize`.
+pub fn fix_link(link: &str, rules: &[LinkRule]) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let url = sanitize::sanitize(&url);
+    rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .find_map(|rule| rule.apply(&url))
+}
+
+// Byte ranges of a message that are code (`inline`, ``double`` or ```fenced```) or
+// ||spoilers||. Either may span several words or lines.
+fn hidden_spans(content: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while let Some(rest) = content.get(index..).filter(|rest| !rest.is_empty()) {
+        // Code runs up to the same number of backticks it opened with
+        let marker = if rest.starts_with('`') {
+            &rest[..rest.len() - rest.trim_start_matches('`').len()]
+        } else if rest.starts_with("||") {
+            "||"
+        } else {
+            index += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+        match rest[marker.len()..].find(marker) {
+            Some(length) => {
+                let end = index + marker.len() + length + marker.len();
+                spans.push(index..end);
+                index = end;
+            }
+            // Unclosed markers are shown as they are
+            None => index += marker.len(),
+        }
+    }
+    spans
+}
+
+// Links in a message that Discord would embed. Links in <angle brackets>, spoilers
+// or code don't embed, so they're left alone.
+pub fn find_links(content: &str) -> Vec<&str> {
+    let hidden = hidden_spans(content);
+    content
+        .split_whitespace()
+        .filter_map(|word| {
+            let start = word.find("http://").or_else(|| word.find("https://"))?;
+            if word[..start].ends_with('<') {
+                return None;
+            }
+            // Where the link is in the whole message
+            let offset = word.as_ptr() as usize - content.as_ptr() as usize + start;
+            if hidden.iter().any(|span| span.contains(&offset)) {
+                return None;
+            }
+            // A spoiler or code straight after the link isn't part of it
+            let link = word[start..].split(['|', '`']).next().unwrap_or_default();
+            // Punctuation and markdown closing the sentence or link around it
+            Some(link.trim_end_matches([')', '.', ',', '!', '?', '*', '_', '~']))
+        })
+        .collect()
+}
+
+fn delete_button(poster: UserId) -> CreateButton {
+    CreateButton::new(format!("{}{}", DELETE_BUTTON_PREFIX, poster))
+        .label("Delete")
+        .emoji('🗑')
+        .style(ButtonStyle::Secondary)
+}
+
+// Reply to links posted in chat with versions that embed properly
+pub async fn fix_links(ctx: &SerenityContext, message: &Message, data: &Data) -> Result<(), Error> {
+    if message.author.bot {
+        return Ok(());
+    }
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+    // Cheap check before touching the database
+    let links = find_links(&message.content);
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let mode = data.db.get_link_fixing(guild_id).await?;
+    if mode == LinkFixMode::Off || data.db.get_link_fix_optout(message.author.id).await? {
+        return Ok(());
+    }
+
+    let rules = guild_rules(&data.db, guild_id).await?;
+    let mut fixed: Vec<String> = Vec::new();
+    let mut embedded: Vec<String> = Vec::new();
+    let mut embeds: Vec<CreateEmbed> = Vec::new();
+    for link in links {
+        if fixed.len() + embeds.len() >= MAX_LINKS {
+            break;
+        }
+        let Some(fixed_link) = fix_link(link, &rules) else {
+            continue;
+        };
+
+        // Posts get an embed of our own, or the fixed link if the API can't help
+        if let Some((screen_name, id)) =
+            tweets::tweet_path(link).filter(|_| mode == LinkFixMode::Embed)
+        {
+            if embedded.contains(&id) {
+                continue;
+            }
+            match data.tweets.get_tweet(&screen_name, &id).await {
+                Ok(Some(tweet)) => {
+                    embeds.push(tweets::build_tweet_embed(&tweet).await);
+                    embedded.push(id);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => println!("Error fetching post {}: {:?}", id, err),
+            }
+        }
+
+        if !fixed.contains(&fixed_link) {
+            fixed.push(fixed_link);
+        }
+    }
+    if fixed.is_empty() && embeds.is_empty() {
+        return Ok(());
+    }
+
+    let mut reply = CreateMessage::new()
+        .embeds(embeds)
+        .reference_message(message)
+        .allowed_mentions(CreateAllowedMentions::new())
+        .components(vec![CreateActionRow::Buttons(vec![delete_button(
+            message.author.id,
+        )])]);
+    if !fixed.is_empty() {
+        reply = reply.content(fixed.join("\n"));
+    }
+    message.channel_id.send_message(ctx, reply).await?;
+
+    if mode.hides_original() {
+        // The reply is already up, so missing permissions only cost the tidy-up
+        if let Err(err) = message
+            .channel_id
+            .edit_message(ctx, message.id, EditMessage::new().suppress_embeds(true))
+            .await
+        {
+            println!("Error hiding embeds of message {}: {:?}", message.id, err);
+        }
+    }
+
+    Ok(())
+}
+
+// Handle the delete button on a fixed link. Like the reminder buttons, it has to keep
+// working after a restart, so it's matched by custom ID rather than with a collector.
+pub async fn handle_button(
+    ctx: &SerenityContext,
+    interaction: &ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(poster) = interaction
+        .data
+        .custom_id
+        .strip_prefix(DELETE_BUTTON_PREFIX)
+        .and_then(|id| id.parse::<u64>().ok())
+        .map(UserId::new)
+    else {
+        return Ok(());
+    };
+
+    // Moderators can tidy up too
+    let is_moderator = interaction
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_messages());
+    if interaction.user.id != poster && !is_moderator {
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("Only the person who posted the link can delete this.")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+    interaction.message.delete(ctx).await?;
+
+    // Bring back the embeds we hid
+    let Some(guild_id) = interaction.guild_id else {
+        return Ok(());
+    };
+    let original = interaction
+        .message
+        .message_reference
+        .as_ref()
+        .and_then(|reference| reference.message_id);
+    if let Some(original) = original {
+        if data.db.get_link_fixing(guild_id).await?.hides_original() {
+            interaction
+                .channel_id
+                .edit_message(ctx, original, EditMessage::new().suppress_embeds(false))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("link_fixing_opt_in", "link_fixing_opt_out"),
+    subcommand_required
+)]
+pub async fn link_fixing(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "opt_in", ephemeral)]
+pub async fn link_fixing_opt_in(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.data()
+        .db
+        .set_link_fix_optout(ctx.author().id, false)
+        .await?;
+
+    let mut content = "I'll reply to links you post with versions that embed properly.".to_owned();
+    if let Some(guild_id) = ctx.guild_id() {
+        if ctx.data().db.get_link_fixing(guild_id).await? == LinkFixMode::Off {
+            content.push_str(" Link fixing is currently turned off in this server, so nothing happens here until an admin turns it on.");
+        }
+    }
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "opt_out", ephemeral)]
+pub async fn link_fixing_opt_out(ctx: Context<'_>) -> Result<(), Error> {
+    let content = if ctx
+        .data()
+        .db
+        .set_link_fix_optout(ctx.author().id, true)
+        .await?
+    {
+        "I'll leave the links you post alone, in every server."
+    } else {
+        "I was already leaving your links alone."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
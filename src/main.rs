@@ -1,13 +1,26 @@
 #![warn(clippy::str_to_string)]
 
 mod admin;
+mod audit;
+mod birthday;
+mod db;
+mod dst;
+mod gazetteer;
+mod linkfix;
 mod media;
 mod misc;
+mod reminders;
+mod sanitize;
+mod scheduler;
+mod timeparse;
+mod tweets;
 mod utils;
+mod worldclock;
 
 use poise::serenity_prelude as serenity;
 
 use dotenv::var;
+use std::sync::Arc;
 
 // Types used by all command functions
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -15,7 +28,9 @@ type Context<'a> = poise::Context<'a, Data, Error>;
 
 // Custom user data passed to all command functions
 pub struct Data {
-    //votes: Mutex<HashMap<String, u32>>,
+    db: db::Database,
+    scheduler: scheduler::Scheduler,
+    tweets: Arc<dyn tweets::TweetApi>,
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
@@ -39,18 +54,43 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
 async fn main() {
     dotenv::dotenv().ok();
 
+    let database_path = var("DATABASE_PATH").unwrap_or_else(|_| "dunebot.db".to_owned());
+    let database = db::Database::open(&database_path).expect("Failed to open database");
+    let tweets_api_url =
+        var("FXTWITTER_API_URL").unwrap_or_else(|_| tweets::DEFAULT_API_URL.to_owned());
+    let legacy_guild = var("LEGACY_GUILD_ID")
+        .ok()
+        .and_then(|id| id.trim().parse::<u64>().ok())
+        .filter(|&id| id != 0)
+        .map(serenity::GuildId::new);
+
     // FrameworkOptions contains all of poise's configuration option in one struct
     // Every option can be omitted to use its default value
     let options = poise::FrameworkOptions {
         commands: vec![
             admin::say(),
-            admin::start_birthday_reminders(),
+            admin::birthday_reminders(),
+            admin::birthday_messages(),
+            admin::settings(),
+            admin::jobs(),
+            admin::announcement(),
+            admin::link_rules(),
+            admin::permissions(),
+            admin::audit(),
+            birthday::birthday(),
+            birthday::anniversary(),
             media::kino(),
             media::book(),
             media::image(),
             misc::timezone(),
             misc::timezones(),
+            misc::time(),
+            misc::whenmeet(),
+            misc::timestamp(),
             misc::fix_twitter_link(),
+            linkfix::link_fixing(),
+            reminders::remindme(),
+            reminders::reminders(),
         ],
         prefix_options: poise::PrefixFrameworkOptions {
             ..Default::default()
@@ -67,6 +107,9 @@ async fn main() {
         post_command: |ctx| {
             Box::pin(async move {
                 println!("Executed command {}!", ctx.command().qualified_name);
+                if let Err(err) = audit::record_command(ctx).await {
+                    println!("Error recording command in the audit log: {:?}", err);
+                }
             })
         },
         // Every command invocation must pass this check to continue execution
@@ -81,12 +124,41 @@ async fn main() {
         // Enforce command checks even for owners (enforced by default)
         // Set to true to bypass checks, which is useful for testing
         skip_checks_for_owners: false,
-        event_handler: |_ctx, event, _framework, _data| {
+        event_handler: |ctx, event, _framework, data| {
             Box::pin(async move {
                 println!(
                     "Got an event in event handler: {:?}",
                     event.snake_case_name()
                 );
+                match event {
+                    // Each handler is independent, so one failing mustn't skip the others
+                    serenity::FullEvent::Message { new_message } => {
+                        if let Err(err) = misc::offer_time_conversion(ctx, new_message, data).await
+                        {
+                            println!("Error offering time conversion: {:?}", err);
+                        }
+                        if let Err(err) = linkfix::fix_links(ctx, new_message, data).await {
+                            println!("Error fixing links: {:?}", err);
+                        }
+                        if let Err(err) = media::unfurl_links(ctx, new_message, data).await {
+                            println!("Error unfurling links: {:?}", err);
+                        }
+                    }
+                    serenity::FullEvent::ReactionAdd { add_reaction } => {
+                        misc::convert_reacted_times(ctx, add_reaction, data).await?;
+                    }
+                    serenity::FullEvent::InteractionCreate {
+                        interaction: serenity::Interaction::Component(component),
+                    } => {
+                        if let Err(err) = reminders::handle_button(ctx, component, data).await {
+                            println!("Error handling reminder button: {:?}", err);
+                        }
+                        if let Err(err) = linkfix::handle_button(ctx, component, data).await {
+                            println!("Error handling link fixing button: {:?}", err);
+                        }
+                    }
+                    _ => {}
+                }
                 Ok(())
             })
         },
@@ -98,8 +170,38 @@ async fn main() {
             Box::pin(async move {
                 println!("Logged in as {}", _ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+
+                // Pull in the CSV files from before the bot had a database
+                database
+                    .import_legacy_csv("timezones.csv", "birthdays.csv", legacy_guild)
+                    .await?;
+
+                let scheduler = scheduler::Scheduler::new(database.clone());
+                scheduler
+                    .add(scheduler::NewJob {
+                        name: Some("dst_announcements".to_owned()),
+                        guild_id: None,
+                        created_by: None,
+                        kind: scheduler::JobKind::DstAnnouncements,
+                        schedule: scheduler::Schedule::Interval {
+                            seconds: 6 * 60 * 60,
+                        },
+                        catch_up: scheduler::CatchUp::RunOnce,
+                        first_run: Some(chrono::Utc::now()),
+                    })
+                    .await?;
+
+                // Guilds that turned reminders on before they were scheduled jobs
+                for config in database.get_enabled_birthday_configs().await? {
+                    birthday::start_reminders(&scheduler, config.guild_id).await?;
+                }
+
+                scheduler.start(ctx.clone());
+
                 Ok(Data {
-                   // votes: Mutex::new(HashMap::new()),
+                    db: database,
+                    scheduler,
+                    tweets: Arc::new(tweets::FxTwitterClient::new(&tweets_api_url)?),
                 })
             })
         })
@@ -107,8 +209,11 @@ async fn main() {
         .build();
 
     let token = var("DISCORD_TOKEN").expect("Expected a token in the environment");
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+    // Both privileged intents must be turned on in the developer portal, see the README.
+    // Server members is needed to list members in bulk, e.g. for `/timezones`
+    let intents = serenity::GatewayIntents::non_privileged()
+        | serenity::GatewayIntents::MESSAGE_CONTENT
+        | serenity::GatewayIntents::GUILD_MEMBERS;
 
     let client = serenity::ClientBuilder::new(token, intents)
         .framework(framework)
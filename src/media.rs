@@ -1,7 +1,11 @@
+use crate::linkfix;
+use crate::sanitize;
 use crate::utils;
-use crate::{Context, Error};
+use crate::{Context, Data, Error};
 use poise::CreateReply;
-use serenity::all::CreateEmbed;
+use reqwest::Url;
+use serenity::all::{CreateAllowedMentions, CreateEmbed, CreateMessage, Message};
+use serenity::client::Context as SerenityContext;
 
 use dotenv::var;
 
@@ -10,6 +14,7 @@ use tmdb_api::client::reqwest::ReqwestExecutor;
 use tmdb_api::client::Client;
 use tmdb_api::movie::details::MovieDetails;
 use tmdb_api::movie::search::MovieSearch;
+use tmdb_api::movie::{Movie, MovieBase};
 use tmdb_api::prelude::Command;
 
 use chrono::Datelike;
@@ -204,6 +209,60 @@ fn truncate_description(description: &str) -> String {
     }
 }
 
+async fn build_movie_embed(
+    client: &Client<ReqwestExecutor>,
+    movie_id: u64,
+) -> Result<CreateEmbed, Error> {
+    let details = MovieDetails::new(movie_id)
+        .execute(client)
+        .await
+        .map_err(|_| "Error fetching movie details")?;
+    Ok(movie_embed(&details.inner, Some(&details)).await)
+}
+
+// A film's card from what a search finds, plus its budget, revenue, runtime and IMDb
+// link if its details could be fetched
+async fn movie_embed(movie: &MovieBase, details: Option<&Movie>) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+
+    if let Some(release_date) = &movie.release_date {
+        let title_with_year = format!("{} ({})", movie.title, release_date.year());
+        embed = embed.title(&title_with_year);
+    } else {
+        embed = embed.title(&movie.title);
+    }
+
+    embed = embed.field("Description", &movie.overview, false);
+
+    if let Some(details) = details {
+        let budget = utils::format_currency(details.budget);
+        embed = embed.field("Budget", format!("${}", budget), true);
+
+        let revenue = utils::format_currency(details.revenue);
+        embed = embed.field("Revenue", format!("${}", revenue), true);
+
+        if let Some(runtime) = details.runtime {
+            embed = embed.field("Runtime", format!("{} minutes", runtime), true);
+        }
+
+        if let Some(imdb_id) = &details.imdb_id {
+            let imdb_link = format!("https://www.imdb.com/title/{}", imdb_id);
+            embed = embed.url(imdb_link);
+        }
+    }
+
+    if let Some(poster_path) = &movie.poster_path {
+        let poster_url = format!("https://image.tmdb.org/t/p/original{}", poster_path);
+        embed = embed.image(&poster_url);
+
+        if let Ok(primary_color) = utils::get_image_primary_color(&poster_url).await {
+            embed = embed.color(primary_color);
+        }
+    }
+
+    embed.footer(CreateEmbedFooter::new("Data sourced from TMDb"))
+}
+
 #[poise::command(slash_command)]
 pub async fn kino(
     ctx: Context<'_>,
@@ -245,44 +304,18 @@ pub async fn kino(
         return Ok(());
     };
 
-    let mut embed = CreateEmbed::default();
-
-    if let Some(release_date) = &item.inner.release_date {
-        let title_with_year = format!("{} ({})", item.inner.title, release_date.year());
-        embed = embed.title(&title_with_year);
-    } else {
-        embed = embed.title(&item.inner.title);
-    }
-
-    embed = embed.field("Description", &item.inner.overview, false);
-
-    if let Ok(details) = MovieDetails::new(item.inner.id).execute(&client).await {
-        let budget = utils::format_currency(details.budget);
-        embed = embed.field("Budget", format!("${}", budget), true);
-
-        let revenue = utils::format_currency(details.revenue);
-        embed = embed.field("Revenue", format!("${}", revenue), true);
-
-        if let Some(runtime) = details.runtime {
-            embed = embed.field("Runtime", format!("{} minutes", runtime), true);
-        }
-
-        if let Some(imdb_id) = details.imdb_id {
-            let imdb_link = format!("https://www.imdb.com/title/{}", imdb_id);
-            embed = embed.url(imdb_link);
+    // The search result is enough for a card without the extra details
+    let details = match MovieDetails::new(item.inner.id).execute(&client).await {
+        Ok(details) => Some(details),
+        Err(err) => {
+            println!(
+                "Error fetching details of movie {}: {:?}",
+                item.inner.id, err
+            );
+            None
         }
-    }
-
-    if let Some(poster_path) = &item.inner.poster_path {
-        let poster_url = format!("https://image.tmdb.org/t/p/original{}", poster_path);
-        embed = embed.image(&poster_url);
-
-        if let Ok(primary_color) = utils::get_image_primary_color(&poster_url).await {
-            embed = embed.color(primary_color);
-        }
-    }
-
-    embed = embed.footer(CreateEmbedFooter::new("Data sourced from TMDb"));
+    };
+    let embed = movie_embed(&item.inner, details.as_ref()).await;
 
     ctx.send(CreateReply::default().embed(embed)).await?;
 
@@ -338,3 +371,173 @@ pub async fn image(
         .await?;
     Ok(())
 }
+
+// Cards posted for one message at most
+const MAX_UNFURLS: usize = 3;
+
+// A link to a book or film page that can be shown as a card
+#[derive(PartialEq)]
+enum MediaLink {
+    Goodreads(String),
+    Imdb(String),
+    Tmdb(u64),
+    Letterboxd(String),
+}
+
+fn media_link(link: &str) -> Option<MediaLink> {
+    let url = Url::parse(link).ok()?;
+    let url = sanitize::sanitize(&url);
+    let host = url.host_str()?;
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(host);
+    let segments: Vec<&str> = url
+        .path_segments()?
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    match (host, &segments[..]) {
+        ("goodreads.com", ["book", "show", book, ..]) => Some(MediaLink::Goodreads(format!(
+            "https://www.goodreads.com/book/show/{}",
+            book
+        ))),
+        ("imdb.com", ["title", id, ..]) if id.starts_with("tt") => {
+            Some(MediaLink::Imdb((*id).to_owned()))
+        }
+        // e.g. /movie/550-fight-club
+        ("themoviedb.org", ["movie", id, ..]) => id
+            .split('-')
+            .next()
+            .and_then(|id| id.parse().ok())
+            .map(MediaLink::Tmdb),
+        // Films, and members' pages about them like /someone/film/fight-club
+        ("letterboxd.com", ["film", film, ..] | [_, "film", film, ..]) => Some(
+            MediaLink::Letterboxd(format!("https://letterboxd.com/film/{}/", film)),
+        ),
+        ("boxd.it", [_]) => Some(MediaLink::Letterboxd(url.to_string())),
+        _ => None,
+    }
+}
+
+// TMDb's ID for a film on IMDb
+async fn find_tmdb_id_by_imdb_id(tmdb_api_key: &str, imdb_id: &str) -> Result<Option<u64>, Error> {
+    let url = format!(
+        "https://api.themoviedb.org/3/find/{}?external_source=imdb_id&api_key={}",
+        encode(imdb_id),
+        tmdb_api_key
+    );
+    let res = reqwest::get(&url).await?.error_for_status()?;
+    let text = res.text().await?;
+    let json: serde_json::Value = serde_json::from_str(&text)?;
+
+    Ok(json
+        .get("movie_results")
+        .and_then(|results| results.as_array())
+        .and_then(|results| results.first())
+        .and_then(|movie| movie.get("id"))
+        .and_then(|id| id.as_u64()))
+}
+
+// TMDb's ID for a film on Letterboxd, which its page carries in a data attribute
+async fn get_letterboxd_tmdb_id(letterboxd_url: &str) -> Result<Option<u64>, Error> {
+    let res = reqwest::get(letterboxd_url).await?.error_for_status()?;
+    let text = res.text().await?;
+
+    let document = Html::parse_document(&text);
+    let tmdb_selector = Selector::parse("[data-tmdb-id]").unwrap();
+    let tmdb_id = document.select(&tmdb_selector).next().and_then(|el| {
+        // TV shows are on Letterboxd too, but their IDs aren't film IDs
+        let is_movie = el
+            .value()
+            .attr("data-tmdb-type")
+            .is_none_or(|kind| kind == "movie");
+        el.value()
+            .attr("data-tmdb-id")
+            .filter(|_| is_movie)
+            .and_then(|id| id.parse().ok())
+    });
+
+    Ok(tmdb_id)
+}
+
+// The card for a media link, like `/book` and `/kino` would post.
+// None if the book or film can't be found, or there's no TMDb key for films.
+async fn build_media_embed(link: MediaLink) -> Result<Option<CreateEmbed>, Error> {
+    if let MediaLink::Goodreads(goodreads_url) = link {
+        let book = get_book(&goodreads_url).await?;
+        if book.title.is_none() {
+            return Ok(None);
+        }
+        return Ok(Some(build_book_embed(&book).await?));
+    }
+
+    let Ok(tmdb_api_key) = var("TMDB_API_KEY") else {
+        return Ok(None);
+    };
+    let movie_id = match link {
+        MediaLink::Tmdb(movie_id) => Some(movie_id),
+        MediaLink::Imdb(imdb_id) => find_tmdb_id_by_imdb_id(&tmdb_api_key, &imdb_id).await?,
+        MediaLink::Letterboxd(letterboxd_url) => get_letterboxd_tmdb_id(&letterboxd_url).await?,
+        MediaLink::Goodreads(_) => None,
+    };
+    let Some(movie_id) = movie_id else {
+        return Ok(None);
+    };
+
+    let client = Client::<ReqwestExecutor>::new(tmdb_api_key);
+    Ok(Some(build_movie_embed(&client, movie_id).await?))
+}
+
+// Answer book and film links posted in channels that opted in with their cards
+pub async fn unfurl_links(
+    ctx: &SerenityContext,
+    message: &Message,
+    data: &Data,
+) -> Result<(), Error> {
+    if message.author.bot || message.guild_id.is_none() {
+        return Ok(());
+    }
+    // The same link pasted twice only gets one card
+    let mut links: Vec<MediaLink> = Vec::new();
+    for link in linkfix::find_links(&message.content)
+        .into_iter()
+        .filter_map(media_link)
+    {
+        if links.len() >= MAX_UNFURLS {
+            break;
+        }
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    // Cheap check before touching the database
+    if links.is_empty() || !data.db.get_link_previews(message.channel_id).await? {
+        return Ok(());
+    }
+
+    let mut embeds = Vec::new();
+    for link in links {
+        match build_media_embed(link).await {
+            Ok(Some(embed)) => embeds.push(embed),
+            Ok(None) => {}
+            Err(err) => println!("Error building card for a link: {:?}", err),
+        }
+    }
+    if embeds.is_empty() {
+        return Ok(());
+    }
+
+    message
+        .channel_id
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .embeds(embeds)
+                .reference_message(message)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -1,26 +1,44 @@
-use crate::{Context, Error};
+use crate::db::Database;
+use crate::gazetteer;
+use crate::linkfix;
+use crate::sanitize;
+use crate::timeparse;
+use crate::utils;
+use crate::worldclock;
+use crate::{Context, Data, Error};
 
-use chrono::Utc;
-use csv::Reader;
-use serenity::all::Member;
-use std::fs::File;
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, Offset, Timelike, Utc};
+use serenity::all::{
+    ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow,
+    CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, GuildId, Member, Message, Reaction, ReactionType,
+    Role, UserId,
+};
+use serenity::client::Context as SerenityContext;
+use std::time::Duration;
 
-use chrono_tz::Tz;
+use chrono_tz::{Tz, TZ_VARIANTS};
+use futures::future::join_all;
 
 use poise::CreateReply;
 
-async fn get_user_local_time(user_id: &str) -> Result<String, String> {
-    // Open the CSV file
-    let file =
-        File::open("timezones.csv").map_err(|err| format!("Error opening CSV file: {:?}", err))?;
-    let mut rdr = Reader::from_reader(file);
+pub async fn get_user_timezone(db: &Database, user_id: UserId) -> Result<Option<Tz>, Error> {
+    let Some(timezone_str) = db.get_timezone(user_id).await? else {
+        return Ok(None);
+    };
+    let tz = timezone_str
+        .parse()
+        .map_err(|_| format!("Invalid timezone: {}", timezone_str))?;
+    Ok(Some(tz))
+}
 
+async fn get_user_local_time(db: &Database, user_id: UserId) -> Result<String, String> {
     // Find the timezone for the user
-    let timezone_str = rdr
-        .records()
-        .filter_map(Result::ok)
-        .find(|record| record.get(0) == Some(user_id))
-        .and_then(|record| record.get(1).map(|s| s.to_owned()))
+    let timezone_str = db
+        .get_timezone(user_id)
+        .await
+        .map_err(|err| format!("Error reading timezones: {:?}", err))?
         .ok_or_else(|| "Timezone not found".to_owned())?;
 
     // Parse the timezone
@@ -35,14 +53,21 @@ async fn get_user_local_time(user_id: &str) -> Result<String, String> {
     Ok(formatted_time)
 }
 
-#[poise::command(slash_command)]
-pub async fn timezone(
+#[poise::command(
+    slash_command,
+    subcommands("timezone_get", "timezone_set", "timezone_clear"),
+    subcommand_required
+)]
+pub async fn timezone(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "get")]
+pub async fn timezone_get(
     ctx: Context<'_>,
     #[description = "The user you wish to get the timezone of"] user: Member,
 ) -> Result<(), Error> {
-    let user_id = user.user.id.to_string();
-
-    match get_user_local_time(&user_id).await {
+    match get_user_local_time(&ctx.data().db, user.user.id).await {
         Ok(local_time) => {
             ctx.send(CreateReply::default().content(format!(
                 "The current time and date for {} is: {}",
@@ -62,75 +87,888 @@ pub async fn timezone(
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn timezones(ctx: Context<'_>) -> Result<(), Error> {
-    let file = match File::open("timezones.csv") {
-        Ok(file) => file,
+// Match a timezone name regardless of case, e.g. "europe/stockholm"
+fn parse_timezone(name: &str) -> Option<Tz> {
+    let name = name.trim();
+    TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(name))
+        .copied()
+}
+
+async fn autocomplete_timezone<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = &'static str> + 'a {
+    let partial = partial.to_lowercase();
+    TZ_VARIANTS
+        .iter()
+        .map(|tz| tz.name())
+        .filter(move |name| name.to_lowercase().contains(&partial))
+        .take(25)
+}
+
+async fn autocomplete_city(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    gazetteer::suggest(partial)
+}
+
+#[poise::command(slash_command, rename = "set", ephemeral)]
+pub async fn timezone_set(
+    ctx: Context<'_>,
+    #[description = "Your IANA timezone, e.g. Europe/Stockholm"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: Option<String>,
+    #[description = "A city or country near you, if you don't know your timezone"]
+    #[autocomplete = "autocomplete_city"]
+    city: Option<String>,
+) -> Result<(), Error> {
+    let tz = match (timezone, city) {
+        (Some(timezone), _) => match parse_timezone(&timezone) {
+            Some(tz) => tz,
+            None => {
+                ctx.send(CreateReply::default().content(format!(
+                    "`{}` is not a valid timezone. Pick one from the suggestions, e.g. `Europe/Stockholm`.",
+                    timezone
+                )))
+                .await?;
+                return Ok(());
+            }
+        },
+        (None, Some(city)) => match pick_place(ctx, &city).await? {
+            Some(tz) => tz,
+            None => return Ok(()),
+        },
+        (None, None) => {
+            ctx.send(CreateReply::default().content("Please give either a timezone or a city."))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    ctx.data()
+        .db
+        .set_timezone(ctx.author().id, tz.name())
+        .await?;
+
+    let local_time = Utc::now().with_timezone(&tz).format("%H:%M %d/%m");
+    ctx.send(CreateReply::default().content(format!(
+        "Your timezone is now set to {}. It is currently {} there.",
+        tz.name(),
+        local_time
+    )))
+    .await?;
+
+    Ok(())
+}
+
+// Resolve a city or country through the gazetteer, asking the user to choose
+// with a select menu when the name is ambiguous.
+// Returns None if nothing was picked; the user has already been told why.
+async fn pick_place(ctx: Context<'_>, city: &str) -> Result<Option<Tz>, Error> {
+    let places = gazetteer::lookup(city);
+
+    let Some(first) = places.first() else {
+        ctx.send(CreateReply::default().content(format!(
+            "Couldn't find a place called `{}`. Try a nearby larger city, or give the timezone directly.",
+            city
+        )))
+        .await?;
+        return Ok(None);
+    };
+    if places.len() == 1 {
+        return Ok(Some(first.timezone));
+    }
+
+    let menu_id = format!("{}-place", ctx.id());
+    let options = places
+        .iter()
+        .enumerate()
+        .map(|(index, place)| {
+            CreateSelectMenuOption::new(place.label(), index.to_string())
+                .description(place.timezone.name())
+        })
+        .collect();
+    let menu = CreateSelectMenu::new(&menu_id, CreateSelectMenuKind::String { options })
+        .placeholder("Choose your location");
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(format!("Which `{}` did you mean?", city))
+                .components(vec![CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let Some(interaction) = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .filter(move |interaction| interaction.data.custom_id == menu_id)
+        .timeout(Duration::from_secs(60))
+        .await
+    else {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content("No location chosen, your timezone was not changed.")
+                    .components(Vec::new()),
+            )
+            .await?;
+        return Ok(None);
+    };
+
+    let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind else {
+        return Ok(None);
+    };
+    let Some(place) = values
+        .first()
+        .and_then(|value| value.parse::<usize>().ok())
+        .and_then(|index| places.get(index))
+    else {
+        return Ok(None);
+    };
+
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(format!("Picked {}.", place.label()))
+                    .components(Vec::new()),
+            ),
+        )
+        .await?;
+
+    Ok(Some(place.timezone))
+}
+
+#[poise::command(slash_command, rename = "clear", ephemeral)]
+pub async fn timezone_clear(ctx: Context<'_>) -> Result<(), Error> {
+    let content = if ctx.data().db.clear_timezone(ctx.author().id).await? {
+        "Your timezone has been cleared."
+    } else {
+        "You don't have a timezone set."
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+// A time and optional date (defaulting to today) as typed by a user, in the given timezone
+fn parse_local_datetime(time: &str, date: Option<&str>, tz: &Tz) -> Result<DateTime<Tz>, String> {
+    let time_of_day = timeparse::parse_time_of_day(time)
+        .ok_or_else(|| format!("Couldn't understand the time `{}`", time))?;
+
+    let today = Utc::now().with_timezone(tz).date_naive();
+    let day = match date {
+        Some(date) => timeparse::parse_date(date, today)
+            .ok_or_else(|| format!("Couldn't understand the date `{}`", date))?,
+        None => today,
+    };
+
+    timeparse::localize(NaiveDateTime::new(day, time_of_day), tz)
+        .ok_or_else(|| format!("That time doesn't exist in {}", tz.name()))
+}
+
+#[poise::command(slash_command, subcommands("time_convert"), subcommand_required)]
+pub async fn time(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "convert")]
+pub async fn time_convert(
+    ctx: Context<'_>,
+    #[description = "The time to convert, e.g. 20:00 or 8pm"] time: String,
+    #[description = "The members to convert for, e.g. @alice @bob"] to: String,
+    #[description = "The date, e.g. tomorrow, friday or 14/03 (defaults to today)"] date: Option<
+        String,
+    >,
+    #[description = "The timezone the time is in (defaults to yours)"]
+    #[autocomplete = "autocomplete_timezone"]
+    from_zone: Option<String>,
+    #[description = "The member whose timezone the time is in"] from: Option<Member>,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
+
+    // Work out which timezone the given time is in
+    let source_tz = match (&from_zone, &from) {
+        (Some(zone), _) => {
+            parse_timezone(zone).ok_or(format!("`{}` is not a valid timezone", zone))
+        }
+        (None, Some(member)) => get_user_timezone(db, member.user.id)
+            .await?
+            .ok_or(format!("{} hasn't registered a timezone", member.user.name)),
+        (None, None) => get_user_timezone(db, ctx.author().id).await?.ok_or(
+            "You haven't registered a timezone, use `/timezone set` or give `from_zone`".to_owned(),
+        ),
+    };
+    let source_tz = match source_tz {
+        Ok(tz) => tz,
         Err(err) => {
-            println!("Error opening CSV file: {:?}", err);
-            ctx.send(CreateReply::default().content("Failed to open the timezones file."))
+            ctx.send(CreateReply::default().content(err).ephemeral(true))
                 .await?;
             return Ok(());
         }
     };
 
-    let mut rdr = csv::Reader::from_reader(file);
-    let mut response = String::new();
+    let source_time = match parse_local_datetime(&time, date.as_deref(), &source_tz) {
+        Ok(source_time) => source_time,
+        Err(err) => {
+            ctx.send(CreateReply::default().content(err).ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+    };
 
-    // Iterate through all records in the CSV file
-    for result in rdr.records() {
-        let record = match result {
-            Ok(record) => record,
-            Err(err) => {
-                println!("Error reading record: {:?}", err);
-                continue;
+    let targets = utils::parse_user_mentions(&to);
+    if targets.is_empty() {
+        ctx.send(
+            CreateReply::default()
+                .content("Mention at least one member to convert for")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let unix = source_time.timestamp();
+    let mut response = format!(
+        "{} in {} is {} ({}) for you.\n",
+        source_time.format("%H:%M %a %d/%m"),
+        source_tz.name(),
+        utils::discord_timestamp(unix, 'F'),
+        utils::discord_timestamp(unix, 'R'),
+    );
+
+    for user_id in targets {
+        match get_user_timezone(db, user_id).await? {
+            Some(tz) => {
+                let local_time = source_time.with_timezone(&tz);
+                response.push_str(&format!(
+                    "- <@{}>: {} ({})\n",
+                    user_id,
+                    local_time.format("%H:%M %a %d/%m"),
+                    tz.name()
+                ));
             }
-        };
+            None => {
+                response.push_str(&format!("- <@{}>: no timezone registered\n", user_id));
+            }
+        }
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
 
-        // Extract user ID
-        let user_id = match record.get(0) {
-            Some(id) => id.to_string(),
-            None => continue,
+#[poise::command(slash_command, ephemeral)]
+pub async fn timestamp(
+    ctx: Context<'_>,
+    #[description = "The time, e.g. 20:00 or 8pm"] time: String,
+    #[description = "The date, e.g. tomorrow, friday or 14/03 (defaults to today)"] date: Option<
+        String,
+    >,
+    #[description = "The timezone the time is in (defaults to yours)"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: Option<String>,
+) -> Result<(), Error> {
+    let tz = match &timezone {
+        Some(zone) => parse_timezone(zone).ok_or(format!("`{}` is not a valid timezone", zone)),
+        None => get_user_timezone(&ctx.data().db, ctx.author().id)
+            .await?
+            .ok_or(
+                "You haven't registered a timezone, use `/timezone set` or give `timezone`"
+                    .to_owned(),
+            ),
+    };
+    let local_time = tz.and_then(|tz| parse_local_datetime(&time, date.as_deref(), &tz));
+    let local_time = match local_time {
+        Ok(local_time) => local_time,
+        Err(err) => {
+            ctx.send(CreateReply::default().content(err)).await?;
+            return Ok(());
+        }
+    };
+
+    let unix = local_time.timestamp();
+    let styles = [
+        ('t', "Short time"),
+        ('T', "Long time"),
+        ('d', "Short date"),
+        ('D', "Long date"),
+        ('f', "Short date/time"),
+        ('F', "Long date/time"),
+        ('R', "Relative"),
+    ];
+
+    let mut preview = String::new();
+    let mut codes = String::new();
+    for (style, name) in styles {
+        let code = utils::discord_timestamp(unix, style);
+        preview.push_str(&format!("{} `{}`: {}\n", name, code, code));
+        codes.push_str(&format!("{}\n", code));
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!(
+            "{} in {}",
+            local_time.format("%H:%M %a %d/%m/%Y"),
+            local_time.timezone().name()
+        ))
+        .description(preview)
+        .field("Copy", format!("```\n{}```", codes), false);
+    ctx.send(CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+// Members of a guild who have registered a timezone, plus any of them whose
+// stored timezone no longer parses
+pub async fn guild_timezones(
+    ctx: &SerenityContext,
+    db: &Database,
+    guild_id: GuildId,
+) -> Result<(Vec<(Member, Tz)>, Vec<(Member, String)>), Error> {
+    let mut guild_members = utils::guild_members(ctx, guild_id).await?;
+    let mut members = Vec::new();
+    let mut invalid = Vec::new();
+    for (user_id, timezone) in db.get_timezones().await? {
+        // Anyone else isn't in this guild
+        let Some(member) = guild_members.remove(&user_id) else {
+            continue;
         };
+        match timezone.parse::<Tz>() {
+            Ok(tz) => members.push((member, tz)),
+            Err(_) => invalid.push((member, timezone)),
+        }
+    }
+    Ok((members, invalid))
+}
+
+// Whether [start, start + length) falls inside waking hours on a single local day.
+// Returns how many minutes of slack are left at the tighter edge.
+fn waking_slack(
+    start: DateTime<Utc>,
+    length: ChronoDuration,
+    tz: &Tz,
+    wake_start: u32,
+    wake_end: u32,
+) -> Option<i64> {
+    let local_start = start.with_timezone(tz);
+    let local_end = (start + length).with_timezone(tz);
+
+    let start_minutes = i64::from(local_start.hour() * 60 + local_start.minute());
+    let end_minutes = if local_end.date_naive() == local_start.date_naive() {
+        i64::from(local_end.hour() * 60 + local_end.minute())
+    } else if local_end.date_naive() == local_start.date_naive().succ_opt()?
+        && local_end.hour() == 0
+        && local_end.minute() == 0
+    {
+        // Ending exactly at midnight
+        24 * 60
+    } else {
+        return None;
+    };
 
-        // Fetch the user from Discord
-        let user = match ctx
-            .serenity_context()
-            .http
-            .get_user(serenity::all::UserId::new(user_id.parse::<u64>().unwrap()))
-            .await
+    let earliest = i64::from(wake_start) * 60;
+    let latest = i64::from(wake_end) * 60;
+    if start_minutes < earliest || end_minutes > latest {
+        return None;
+    }
+    Some((start_minutes - earliest).min(latest - end_minutes))
+}
+
+// Slot starts every half hour over the next `days` days where everyone is awake,
+// best first: the slots with the most slack for the tightest member. Overlapping
+// slots are dropped in favour of better ones.
+fn find_meeting_slots(
+    zones: &[Tz],
+    length: ChronoDuration,
+    days: u32,
+    wake_start: u32,
+    wake_end: u32,
+    now: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    let step = ChronoDuration::minutes(30);
+    // Round up to the next half hour
+    let mut start = now
+        .with_second(0)
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(now);
+    start += ChronoDuration::minutes(i64::from(30 - start.minute() % 30));
+    let end = now + ChronoDuration::days(i64::from(days));
+
+    let mut candidates = Vec::new();
+    while start + length <= end {
+        let slack = zones
+            .iter()
+            .map(|tz| waking_slack(start, length, tz, wake_start, wake_end))
+            .try_fold(i64::MAX, |least, slack| slack.map(|slack| least.min(slack)));
+        if let Some(slack) = slack {
+            candidates.push((start, slack));
+        }
+        start += step;
+    }
+
+    // Stable sort keeps earlier slots first among equally good ones
+    candidates.sort_by_key(|(_, slack)| std::cmp::Reverse(*slack));
+
+    let mut picked: Vec<DateTime<Utc>> = Vec::new();
+    for (start, _) in candidates {
+        if picked
+            .iter()
+            .all(|other| start + length <= *other || *other + length <= start)
         {
-            Ok(user) => user,
-            Err(err) => {
-                println!("Error fetching user for ID {}: {:?}", user_id, err);
-                response.push_str(&format!("Failed to fetch user for ID {}.\n", user_id));
-                continue;
+            picked.push(start);
+        }
+    }
+    picked
+}
+
+#[poise::command(slash_command, guild_only)]
+pub async fn whenmeet(
+    ctx: Context<'_>,
+    #[description = "How long the meeting is, e.g. 2h or 90m"] duration: String,
+    #[description = "The members to meet, e.g. @alice @bob"] members: Option<String>,
+    #[description = "Everyone with this role"] role: Option<Role>,
+    #[description = "How many days ahead to look (default 7)"]
+    #[min = 1]
+    #[max = 14]
+    days: Option<u32>,
+    #[description = "Hour people wake up, local time (default 9)"]
+    #[max = 23]
+    wake_start: Option<u32>,
+    #[description = "Hour people go to bed, local time (default 23)"]
+    #[min = 1]
+    #[max = 24]
+    wake_end: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let days = days.unwrap_or(7);
+    let wake_start = wake_start.unwrap_or(9);
+    let wake_end = wake_end.unwrap_or(23);
+
+    let Some(length) = timeparse::parse_duration(&duration) else {
+        ctx.send(
+            CreateReply::default()
+                .content(format!("Couldn't understand the duration `{}`", duration))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+    if wake_start >= wake_end || length > ChronoDuration::hours(i64::from(wake_end - wake_start)) {
+        ctx.send(
+            CreateReply::default()
+                .content("The meeting doesn't fit inside the waking hours")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+
+    // Everyone asked for, and which of them have a timezone
+    let mut wanted: Vec<UserId> = members
+        .as_deref()
+        .map(utils::parse_user_mentions)
+        .unwrap_or_default();
+    let (registered, _) = guild_timezones(ctx.serenity_context(), &ctx.data().db, guild_id).await?;
+    if let Some(role) = &role {
+        for (member, _) in &registered {
+            if member.roles.contains(&role.id) && !wanted.contains(&member.user.id) {
+                wanted.push(member.user.id);
             }
+        }
+    }
+    if wanted.is_empty() {
+        ctx.send(CreateReply::default().content("Mention some members or pick a role"))
+            .await?;
+        return Ok(());
+    }
+
+    let mut zones = Vec::new();
+    let mut missing = Vec::new();
+    for user_id in &wanted {
+        match registered
+            .iter()
+            .find(|(member, _)| member.user.id == *user_id)
+        {
+            Some((_, tz)) => {
+                if !zones.contains(tz) {
+                    zones.push(*tz);
+                }
+            }
+            None => missing.push(format!("<@{}>", user_id)),
+        }
+    }
+
+    let slots = find_meeting_slots(&zones, length, days, wake_start, wake_end, Utc::now());
+
+    let mut response = format!(
+        "Slots in the next {} days where all {} members are awake ({}:00-{}:00 local):\n",
+        days,
+        wanted.len() - missing.len(),
+        wake_start,
+        wake_end
+    );
+    if slots.is_empty() {
+        response
+            .push_str("No slot works for everyone. Try a shorter meeting or wider waking hours.\n");
+    }
+    let mut best: Vec<DateTime<Utc>> = slots.into_iter().take(5).collect();
+    best.sort();
+    for start in best {
+        let end = start + length;
+        response.push_str(&format!(
+            "- {} - {} ({})\n",
+            utils::discord_timestamp(start.timestamp(), 'F'),
+            utils::discord_timestamp(end.timestamp(), 't'),
+            utils::discord_timestamp(start.timestamp(), 'R'),
+        ));
+    }
+    if !missing.is_empty() {
+        response.push_str(&format!(
+            "Left out because they have no timezone registered: {}\n",
+            missing.join(", ")
+        ));
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+const TIME_DETECTION_EMOJI: char = '🕒';
+
+// Watches chat for times like "game at 8pm tomorrow". When the author has a timezone
+// and the guild has time detection on, react so readers can ask for the time in their zone.
+pub async fn offer_time_conversion(
+    ctx: &SerenityContext,
+    message: &Message,
+    data: &Data,
+) -> Result<(), Error> {
+    if message.author.bot {
+        return Ok(());
+    }
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+    // Cheap check before touching the database
+    if !message.content.chars().any(|c| c.is_ascii_digit()) {
+        return Ok(());
+    }
+    if !data.db.get_time_detection(guild_id).await? {
+        return Ok(());
+    }
+    let Some(tz) = get_user_timezone(&data.db, message.author.id).await? else {
+        return Ok(());
+    };
+
+    let today = message.timestamp.with_timezone(&tz).date_naive();
+    if timeparse::find_times(&message.content, today).is_empty() {
+        return Ok(());
+    }
+
+    message
+        .react(ctx, ReactionType::from(TIME_DETECTION_EMOJI))
+        .await?;
+
+    Ok(())
+}
+
+// Answers the first reader to click the time detection reaction with Discord
+// timestamps, which render in every reader's own timezone.
+pub async fn convert_reacted_times(
+    ctx: &SerenityContext,
+    reaction: &Reaction,
+    data: &Data,
+) -> Result<(), Error> {
+    if !reaction.emoji.unicode_eq(&TIME_DETECTION_EMOJI.to_string()) {
+        return Ok(());
+    }
+    if reaction.user_id == Some(ctx.cache.current_user().id) {
+        return Ok(());
+    }
+
+    let message = reaction.message(ctx).await?;
+
+    // Our own reaction is removed once answered, so only the first click replies
+    let offered = message.reactions.iter().any(|message_reaction| {
+        message_reaction.me
+            && message_reaction
+                .reaction_type
+                .unicode_eq(&TIME_DETECTION_EMOJI.to_string())
+    });
+    if !offered {
+        return Ok(());
+    }
+
+    let Some(tz) = get_user_timezone(&data.db, message.author.id).await? else {
+        return Ok(());
+    };
+
+    // Relative days are relative to when the message was sent
+    let today = message.timestamp.with_timezone(&tz).date_naive();
+    let mut response = String::new();
+    for found in timeparse::find_times(&message.content, today) {
+        let Some(local_time) = timeparse::localize(NaiveDateTime::new(found.date, found.time), &tz)
+        else {
+            continue;
         };
+        let unix = local_time.timestamp();
+        response.push_str(&format!(
+            "{} {} is {} ({})\n",
+            TIME_DETECTION_EMOJI,
+            found.text,
+            utils::discord_timestamp(unix, 'F'),
+            utils::discord_timestamp(unix, 'R'),
+        ));
+    }
+    if response.is_empty() {
+        return Ok(());
+    }
 
-        // Get the local time for the user
-        match get_user_local_time(&user_id).await {
-            Ok(local_time) => {
-                response.push_str(&format!(
-                    "{} : {}\n",
-                    user.name, local_time
-                ));
+    message
+        .channel_id
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .content(response)
+                .reference_message(&message)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    message
+        .channel_id
+        .delete_reaction(ctx, message.id, None, TIME_DETECTION_EMOJI)
+        .await?;
+
+    Ok(())
+}
+
+// The image version of `/timezones`: one row per member, west to east
+// Per avatar, so one slow download can't stall the whole image
+const AVATAR_TIMEOUT: Duration = Duration::from_secs(3);
+
+async fn send_world_clock(
+    ctx: Context<'_>,
+    members: &[(i32, Member, Tz)],
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    // Keep the image a sensible height
+    let members = &members[..members.len().min(25)];
+
+    // All at once, and a slow one is drawn without its avatar rather than holding up the rest
+    let client = reqwest::Client::builder().timeout(AVATAR_TIMEOUT).build()?;
+    let avatars = join_all(members.iter().map(|(_, member, _)| {
+        let avatar_url = member
+            .user
+            .static_avatar_url()
+            .unwrap_or_else(|| member.user.default_avatar_url());
+        let request = client.get(format!("{}?size=64", avatar_url)).send();
+        async move {
+            let avatar: Result<_, reqwest::Error> = async { request.await?.bytes().await }.await;
+            match avatar {
+                Ok(bytes) => Some(bytes.to_vec()),
+                Err(err) => {
+                    println!("Error fetching avatar for {}: {:?}", member.user.name, err);
+                    None
+                }
             }
+        }
+    }))
+    .await;
+
+    let mut rows = Vec::new();
+    let mut legend = String::new();
+    for (index, ((_, member, tz), avatar)) in members.iter().zip(avatars).enumerate() {
+        rows.push(worldclock::ClockRow { tz: *tz, avatar });
+
+        legend.push_str(&format!(
+            "{}. {} - {} ({})\n",
+            index + 1,
+            member.display_name(),
+            now.with_timezone(tz).format("%H:%M %a"),
+            tz.name()
+        ));
+    }
+
+    let png = worldclock::render(&rows, now)?;
+    let embed = CreateEmbed::default()
+        .title("Who is awake")
+        .description(legend)
+        .image("attachment://worldclock.png")
+        .footer(CreateEmbedFooter::new(
+            "The red line is now, each band spans 12 hours either side",
+        ));
+
+    ctx.send(
+        CreateReply::default()
+            .attachment(CreateAttachment::bytes(png, "worldclock.png"))
+            .embed(embed),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// e.g. "UTC+05:30"
+pub fn format_offset(offset_seconds: i32) -> String {
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let minutes = offset_seconds.abs() / 60;
+    format!("UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+// Characters per page of `/timezones`, well under the embed limits
+const PAGE_CHARS: usize = 1500;
+
+// Lay a heading and its list out over `pages`.
+// A list too long for one page carries on onto the next, under its heading again.
+fn add_section(pages: &mut Vec<String>, heading: &str, lines: &[String]) {
+    let mut section = format!("{}\n", heading);
+    let mut section_lines = 0;
+    for line in lines {
+        let line = format!("- {}\n", line);
+        if section_lines > 0 && section.chars().count() + line.chars().count() > PAGE_CHARS {
+            add_to_page(pages, section);
+            section = format!("{} (continued)\n", heading);
+            section_lines = 0;
+        }
+        section.push_str(&line);
+        section_lines += 1;
+    }
+    section.push('\n');
+    add_to_page(pages, section);
+}
+
+fn add_to_page(pages: &mut Vec<String>, section: String) {
+    let page = pages.last_mut().expect("pages is never empty");
+    if !page.is_empty() && page.chars().count() + section.chars().count() > PAGE_CHARS {
+        pages.push(section);
+    } else {
+        page.push_str(&section);
+    }
+}
+
+#[poise::command(slash_command, guild_only)]
+pub async fn timezones(
+    ctx: Context<'_>,
+    #[description = "Show a world clock image of who is awake instead of a list"] image: Option<
+        bool,
+    >,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    ctx.defer().await?;
+
+    let (members, invalid) =
+        match guild_timezones(ctx.serenity_context(), &ctx.data().db, guild_id).await {
+            Ok(timezones) => timezones,
             Err(err) => {
-                response.push_str(&format!(
-                    "Failed to get timezone for {}: {}\n",
-                    user.name, err
-                ));
+                println!("Error reading timezones: {:?}", err);
+                ctx.send(CreateReply::default().content("Failed to read the timezones."))
+                    .await?;
+                return Ok(());
             }
+        };
+
+    if members.is_empty() && invalid.is_empty() {
+        ctx.send(CreateReply::default().content(
+            "Nobody in this server has registered a timezone yet. Use `/timezone set` to add yours.",
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    // Group members by their current UTC offset, west to east
+    let now = Utc::now();
+    let mut groups: Vec<(i32, Vec<String>)> = Vec::new();
+    let mut members: Vec<(i32, Member, Tz)> = members
+        .into_iter()
+        .map(|(member, tz)| {
+            let offset = now.with_timezone(&tz).offset().fix().local_minus_utc();
+            (offset, member, tz)
+        })
+        .collect();
+    members.sort_by(|a, b| {
+        a.0.cmp(&b.0).then_with(|| {
+            a.1.display_name()
+                .to_lowercase()
+                .cmp(&b.1.display_name().to_lowercase())
+        })
+    });
+
+    if image.unwrap_or(false) {
+        return send_world_clock(ctx, &members, now).await;
+    }
+
+    for (offset, member, tz) in members {
+        let line = format!("{} ({})", member.display_name(), tz.name());
+        match groups.last_mut() {
+            Some((group_offset, lines)) if *group_offset == offset => lines.push(line),
+            _ => groups.push((offset, vec![line])),
         }
     }
 
-    // Send the response as a single message
-    if response.is_empty() {
-        response = "No timezones found or failed to process.".to_string();
+    let mut pages: Vec<String> = vec![String::new()];
+    for (offset, lines) in groups {
+        let local_time = now + ChronoDuration::seconds(i64::from(offset));
+        let heading = format!(
+            "**{}** - {}",
+            format_offset(offset),
+            local_time.format("%H:%M %a %d/%m")
+        );
+        add_section(&mut pages, &heading, &lines);
     }
 
-    ctx.send(CreateReply::default().content(response)).await?;
+    // Report broken entries instead of failing the whole listing
+    if !invalid.is_empty() {
+        let lines: Vec<String> = invalid
+            .iter()
+            .map(|(member, timezone)| {
+                format!("{}: invalid timezone `{}`", member.display_name(), timezone)
+            })
+            .collect();
+        // On a page of its own
+        if pages.last().is_some_and(|page| !page.is_empty()) {
+            pages.push(String::new());
+        }
+        add_section(&mut pages, "**Skipped entries**", &lines);
+    }
+
+    let guild_name = ctx
+        .guild()
+        .map(|guild| guild.name.clone())
+        .unwrap_or_else(|| "this server".to_owned());
+    let page_count = pages.len();
+    let embeds = pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, page)| {
+            CreateEmbed::default()
+                .title(format!("Timezones in {}", guild_name))
+                .description(page)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Page {}/{}",
+                    index + 1,
+                    page_count
+                )))
+        })
+        .collect();
+
+    utils::paginate_embeds(ctx, embeds).await?;
 
     Ok(())
 }
@@ -138,18 +976,26 @@ pub async fn timezones(ctx: Context<'_>) -> Result<(), Error> {
 #[poise::command(slash_command)]
 pub async fn fix_twitter_link(
     ctx: Context<'_>,
-    #[description = "The Twitter link to fix"] twitter_link: String,
+    #[description = "The Twitter link to fix (Instagram, TikTok, Reddit and others work too)"]
+    twitter_link: String,
 ) -> Result<(), Error> {
-    let fixed_link = twitter_link
-        .replace("https://x.com/", "https://fxtwitter.com/")
-        .replace("https://twitter.com/", "https://fxtwitter.com/");
-
-    // Remove tracking parameters
-    let fixed_link = fixed_link
-        .split('?')
-        .next()
-        .unwrap_or(&fixed_link)
-        .to_string();
+    // The guild's own rules, or the built-in ones in DMs
+    let rules = match ctx.guild_id() {
+        Some(guild_id) => linkfix::guild_rules(&ctx.data().db, guild_id).await?,
+        None => linkfix::builtin_rules(),
+    };
+    // Links no rule covers still lose their trackers
+    let link = twitter_link.trim();
+    let fixed_link = linkfix::fix_link(link, &rules).or_else(|| sanitize::sanitize_link(link));
+    let Some(fixed_link) = fixed_link else {
+        ctx.send(
+            CreateReply::default()
+                .content("I don't know how to fix that link.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
     ctx.send(CreateReply::default().content(fixed_link)).await?;
 
     Ok(())
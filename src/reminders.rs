@@ -0,0 +1,400 @@
+use crate::db::{Database, Reminder};
+use crate::misc;
+use crate::scheduler::{CatchUp, JobKind, NewJob, Schedule, Scheduler};
+use crate::timeparse;
+use crate::utils;
+use crate::{Context, Data, Error};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use chrono_tz::Tz;
+use poise::CreateReply;
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, Http,
+    MessageReference, MessageReferenceKind,
+};
+use serenity::client::Context as SerenityContext;
+
+// Keeps `/reminders` to one message of buttons
+const MAX_PENDING: usize = 25;
+const MAX_DAYS_AHEAD: i64 = 365;
+// How long to wait before trying again when a reminder couldn't be posted
+const RETRY_MINUTES: i64 = 10;
+// Snooze buttons on a delivered reminder: label and minutes
+const SNOOZES: [(&str, i64); 3] = [("10 minutes", 10), ("1 hour", 60), ("1 day", 24 * 60)];
+
+fn job_name(reminder_id: i64) -> String {
+    format!("reminder:{}", reminder_id)
+}
+
+async fn schedule(scheduler: &Scheduler, reminder: &Reminder) -> Result<(), Error> {
+    scheduler
+        .add(NewJob {
+            name: Some(job_name(reminder.id)),
+            guild_id: None,
+            created_by: Some(reminder.user_id),
+            kind: JobKind::Reminder {
+                reminder_id: reminder.id,
+            },
+            schedule: Schedule::Once,
+            // Better late than never, e.g. if the bot was down when it was due
+            catch_up: CatchUp::RunOnce,
+            first_run: Some(reminder.due_at),
+        })
+        .await?;
+    Ok(())
+}
+
+fn cancel_button(reminder_id: i64) -> CreateButton {
+    CreateButton::new(format!("reminder-cancel-{}", reminder_id))
+        .label("Cancel")
+        .style(ButtonStyle::Secondary)
+}
+
+fn snooze_buttons(reminder_id: i64) -> CreateActionRow {
+    CreateActionRow::Buttons(
+        SNOOZES
+            .iter()
+            .map(|(label, minutes)| {
+                CreateButton::new(format!("reminder-snooze-{}-{}", reminder_id, minutes))
+                    .label(format!("Snooze {}", label))
+                    .style(ButtonStyle::Secondary)
+            })
+            .collect(),
+    )
+}
+
+// When `in`/`at` falls, or a message explaining why it can't be used
+async fn due_at(
+    ctx: Context<'_>,
+    in_: Option<&str>,
+    at: Option<&str>,
+) -> Result<Result<DateTime<Utc>, String>, Error> {
+    let now = Utc::now();
+    let due = match (in_, at) {
+        (Some(in_), None) => {
+            let Some(duration) = timeparse::parse_duration(in_) else {
+                return Ok(Err(format!(
+                    "I couldn't read `{}` as a duration. Try something like `2h30m`, `90m` or `1d`.",
+                    in_
+                )));
+            };
+            // Checked before adding, which overflows for absurd durations
+            if duration > ChronoDuration::days(MAX_DAYS_AHEAD) {
+                return Ok(Err("Reminders can be set at most a year ahead.".to_owned()));
+            }
+            now + duration
+        }
+        (None, Some(at)) => {
+            // Times are read in the member's own timezone
+            let tz = misc::get_user_timezone(&ctx.data().db, ctx.author().id)
+                .await?
+                .unwrap_or(Tz::UTC);
+            let Some(due) = timeparse::resolve_date_time(at, now, &tz) else {
+                return Ok(Err(format!(
+                    "I couldn't read `{}` as a time. Try something like `18:00`, `friday 18:00` or `tomorrow 8pm`.",
+                    at
+                )));
+            };
+            due.with_timezone(&Utc)
+        }
+        _ => {
+            return Ok(Err(
+                "Tell me either `in` (how long from now) or `at` (when), but not both.".to_owned(),
+            ))
+        }
+    };
+
+    if due <= now {
+        return Ok(Err("That time has already passed.".to_owned()));
+    }
+    if due - now > ChronoDuration::days(MAX_DAYS_AHEAD) {
+        return Ok(Err("Reminders can be set at most a year ahead.".to_owned()));
+    }
+    Ok(Ok(due))
+}
+
+#[poise::command(slash_command)]
+pub async fn remindme(
+    ctx: Context<'_>,
+    #[description = "What to remind you about"]
+    #[max_length = 1000]
+    what: String,
+    #[description = "How long from now, e.g. 2h30m, 90m or 1d"]
+    #[rename = "in"]
+    in_: Option<String>,
+    #[description = "When, in your timezone, e.g. \"18:00\", \"friday 18:00\" or \"tomorrow 8pm\""]
+    at: Option<String>,
+    #[description = "Send the reminder by DM instead of in this channel"] dm: Option<bool>,
+) -> Result<(), Error> {
+    let dm = dm.unwrap_or(false);
+
+    let due = match due_at(ctx, in_.as_deref(), at.as_deref()).await? {
+        Ok(due) => due,
+        Err(problem) => {
+            ctx.send(CreateReply::default().content(problem).ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let db = &ctx.data().db;
+    if db.get_pending_reminders(ctx.author().id).await?.len() >= MAX_PENDING {
+        ctx.send(
+            CreateReply::default()
+                .content(format!(
+                    "You already have {} reminders waiting. Cancel some with `/reminders` first.",
+                    MAX_PENDING
+                ))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let id = db
+        .add_reminder(ctx.author().id, ctx.channel_id(), &what, dm, due)
+        .await?;
+    let reminder = db.get_reminder(id).await?.ok_or("Reminder vanished")?;
+    schedule(&ctx.data().scheduler, &reminder).await?;
+
+    let mut content = format!(
+        "⏰ I'll remind you {} ({}): {}",
+        utils::discord_timestamp(due.timestamp(), 'F'),
+        utils::discord_timestamp(due.timestamp(), 'R'),
+        what
+    );
+    if at.is_some()
+        && misc::get_user_timezone(db, ctx.author().id)
+            .await?
+            .is_none()
+    {
+        content.push_str("\nYou haven't set your timezone, so I read that as UTC. Use `/timezone set` to fix that.");
+    }
+
+    // Reminders by DM are private, so the confirmation is too
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(content)
+                .components(vec![CreateActionRow::Buttons(vec![cancel_button(id)])])
+                .allowed_mentions(CreateAllowedMentions::new())
+                .ephemeral(dm),
+        )
+        .await?;
+    if !dm {
+        db.set_reminder_message(id, reply.message().await?.id)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[poise::command(slash_command, ephemeral)]
+pub async fn reminders(ctx: Context<'_>) -> Result<(), Error> {
+    let pending = ctx.data().db.get_pending_reminders(ctx.author().id).await?;
+    if pending.is_empty() {
+        ctx.send(
+            CreateReply::default()
+                .content("You don't have any reminders waiting. Set one with `/remindme`."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let description = pending
+        .iter()
+        .map(|reminder| {
+            format!(
+                "**#{}** {} - {}",
+                reminder.id,
+                utils::discord_timestamp(reminder.due_at.timestamp(), 'R'),
+                reminder.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // One cancel button per reminder, five to a row
+    let buttons: Vec<CreateButton> = pending
+        .iter()
+        .map(|reminder| cancel_button(reminder.id).label(format!("Cancel #{}", reminder.id)))
+        .collect();
+    let rows = buttons
+        .chunks(5)
+        .map(|row| CreateActionRow::Buttons(row.to_vec()))
+        .collect();
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("⏰ Your reminders")
+                    .description(description),
+            )
+            .components(rows),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// Post a reminder that has come due. Run by the scheduler.
+pub async fn deliver(
+    http: &Http,
+    db: &Database,
+    scheduler: &Scheduler,
+    reminder_id: i64,
+) -> Result<(), Error> {
+    // Cancelled since it was scheduled
+    let Some(reminder) = db.get_reminder(reminder_id).await? else {
+        return Ok(());
+    };
+
+    let mut delivered = false;
+    if reminder.dm {
+        let message = CreateMessage::new()
+            .content(format!("⏰ Reminder: {}", reminder.text))
+            .components(vec![snooze_buttons(reminder.id)]);
+        match reminder.user_id.direct_message(http, message).await {
+            Ok(_) => delivered = true,
+            // Fall back to the channel if their DMs are closed
+            Err(err) => println!(
+                "Error sending reminder DM to {}: {:?}",
+                reminder.user_id, err
+            ),
+        }
+    }
+
+    if !delivered {
+        let mut message = CreateMessage::new()
+            .content(format!(
+                "⏰ <@{}>, you asked me to remind you: {}",
+                reminder.user_id, reminder.text
+            ))
+            .components(vec![snooze_buttons(reminder.id)])
+            .allowed_mentions(CreateAllowedMentions::new().users([reminder.user_id]));
+        if let Some(message_id) = reminder.message_id {
+            message = message.reference_message(
+                MessageReference::new(MessageReferenceKind::Default, reminder.channel_id)
+                    .message_id(message_id)
+                    .fail_if_not_exists(false),
+            );
+        }
+        match reminder.channel_id.send_message(http, message).await {
+            Ok(_) => {}
+            // Discord refusing it, e.g. because the channel is gone, won't change by
+            // retrying, so the reminder goes to their DMs instead
+            Err(serenity::Error::Http(err))
+                if err
+                    .status_code()
+                    .is_some_and(|status| status.is_client_error() && status.as_u16() != 429) =>
+            {
+                println!(
+                    "Error posting reminder {} in {}: {:?}",
+                    reminder.id, reminder.channel_id, err
+                );
+                let message = CreateMessage::new()
+                    .content(format!(
+                        "⏰ I couldn't post your reminder in <#{}>, so here it is: {}",
+                        reminder.channel_id, reminder.text
+                    ))
+                    .components(vec![snooze_buttons(reminder.id)]);
+                if let Err(err) = reminder.user_id.direct_message(http, message).await {
+                    println!(
+                        "Error sending reminder DM to {}: {:?}",
+                        reminder.user_id, err
+                    );
+                }
+            }
+            // Anything else may pass, so try again shortly
+            Err(err) => {
+                let due = Utc::now() + ChronoDuration::minutes(RETRY_MINUTES);
+                db.snooze_reminder(reminder.id, due).await?;
+                schedule(
+                    scheduler,
+                    &Reminder {
+                        due_at: due,
+                        ..reminder
+                    },
+                )
+                .await?;
+                return Err(err.into());
+            }
+        }
+    }
+
+    db.mark_reminder_delivered(reminder.id).await
+}
+
+// Handle the cancel and snooze buttons. These have to keep working after a restart,
+// so they are matched by custom ID here rather than with a collector.
+pub async fn handle_button(
+    ctx: &SerenityContext,
+    interaction: &ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(action) = interaction.data.custom_id.strip_prefix("reminder-") else {
+        return Ok(());
+    };
+    let mut parts = action.split('-');
+    let (Some(kind), Some(Ok(reminder_id))) = (parts.next(), parts.next().map(str::parse::<i64>))
+    else {
+        return Ok(());
+    };
+
+    let reminder = data.db.get_reminder(reminder_id).await?;
+    let Some(reminder) = reminder.filter(|reminder| reminder.user_id == interaction.user.id) else {
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("That reminder isn't yours, or it no longer exists.")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    let content = match (kind, parts.next().map(str::parse::<i64>)) {
+        ("cancel", _) => {
+            data.scheduler.cancel_named(&job_name(reminder.id)).await?;
+            data.db.remove_reminder(reminder.id).await?;
+            format!("~~{}~~ Cancelled.", reminder.text)
+        }
+        ("snooze", Some(Ok(minutes))) => {
+            let due = Utc::now() + ChronoDuration::minutes(minutes);
+            data.db.snooze_reminder(reminder.id, due).await?;
+            // Replaces the job if it hasn't gone off yet
+            data.scheduler.cancel_named(&job_name(reminder.id)).await?;
+            let reminder = Reminder {
+                due_at: due,
+                ..reminder
+            };
+            schedule(&data.scheduler, &reminder).await?;
+            format!(
+                "⏰ Snoozed until {}: {}",
+                utils::discord_timestamp(due.timestamp(), 't'),
+                reminder.text
+            )
+        }
+        _ => return Ok(()),
+    };
+
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .embeds(Vec::new())
+                    .components(Vec::new())
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use reqwest::Url;
+
+// Redirects followed in one link, in case they're nested
+const MAX_UNWRAPS: usize = 3;
+
+// Query parameters that only exist for tracking, on any site
+const TRACKING_PARAMS: [&str; 16] = [
+    "fbclid", "gclid", "gclsrc", "dclid", "msclkid", "yclid", "twclid", "igshid", "igsh", "si",
+    "mc_cid", "mc_eid", "_hsenc", "_hsmi", "mkt_tok", "share_id",
+];
+const TRACKING_PREFIXES: [&str; 2] = ["utm_", "hsa_"];
+
+// Query parameters that are only for tracking on some sites, but mean something elsewhere.
+// YouTube's `t` is a timestamp, for example, while Twitter's is a tracking token.
+const SITE_TRACKING_PARAMS: [(&[&str], &[&str]); 4] = [
+    (&["x.com", "twitter.com"], &["s", "t", "ref_src", "ref_url"]),
+    (&["youtube.com", "youtu.be"], &["feature", "pp"]),
+    (
+        &["tiktok.com"],
+        &[
+            "_r",
+            "_t",
+            "is_from_webapp",
+            "is_copy_url",
+            "sender_device",
+            "sender_web_id",
+            "web_id",
+            "u_code",
+            "preview_pb",
+            "share_app_id",
+            "share_item_id",
+            "share_link_id",
+            "social_sharing",
+            "timestamp",
+            "user_id",
+        ],
+    ),
+    (&["reddit.com"], &["ref", "ref_source", "rdt"]),
+];
+
+// Pages that redirect to the link in one of their query parameters: domain, path, parameter.
+// A domain ending in `.*` matches any top-level domain, for Google's country sites.
+const REDIRECTORS: [(&str, &str, &str); 7] = [
+    ("google.*", "/url", "q"),
+    ("google.*", "/url", "url"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("l.messenger.com", "/l.php", "u"),
+    ("l.instagram.com", "/", "u"),
+    ("youtube.com", "/redirect", "q"),
+];
+
+// Whether `host` is `domain` or one of its subdomains
+fn matches_domain(host: &str, domain: &str) -> bool {
+    if let Some(name) = domain.strip_suffix(".*") {
+        let host = host.strip_prefix("www.").unwrap_or(host);
+        return host
+            .strip_prefix(name)
+            .is_some_and(|tld| tld.starts_with('.'));
+    }
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+fn is_web_link(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+// The link a redirect page points to, if `url` is one
+fn unwrap_redirect(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+    let target = REDIRECTORS
+        .iter()
+        .filter(|(domain, path, _)| matches_domain(host, domain) && url.path() == *path)
+        .find_map(|(_, _, param)| {
+            let (_, value) = url.query_pairs().find(|(key, _)| key == param)?;
+            Url::parse(&value).ok()
+        })?;
+    is_web_link(&target).then_some(target)
+}
+
+fn is_tracking_param(host: &str, key: &str) -> bool {
+    TRACKING_PARAMS.contains(&key)
+        || TRACKING_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+        || SITE_TRACKING_PARAMS.iter().any(|(domains, params)| {
+            params.contains(&key) && domains.iter().any(|domain| matches_domain(host, domain))
+        })
+}
+
+// Follow redirect links to where they lead and strip tracking parameters.
+// Everything else is kept as written, e.g. YouTube's `v` and `t`.
+pub fn sanitize(url: &Url) -> Url {
+    let mut url = url.clone();
+    for _ in 0..MAX_UNWRAPS {
+        match unwrap_redirect(&url) {
+            Some(target) => url = target,
+            None => break,
+        }
+    }
+
+    let (Some(host), Some(query)) = (url.host_str(), url.query()) else {
+        return url;
+    };
+    // Filtered as written rather than decoded and re-encoded, so kept values don't change
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !key.is_empty() && !is_tracking_param(host, key)
+        })
+        .collect();
+    let query = kept.join("&");
+
+    url.set_query((!query.is_empty()).then_some(query.as_str()));
+    url
+}
+
+// `sanitize` for a link as text: the cleaned-up link, or None if it isn't a web link
+// or is already clean
+pub fn sanitize_link(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok().filter(is_web_link)?;
+    let cleaned = sanitize(&url);
+    (cleaned != url).then(|| cleaned.to_string())
+}
@@ -0,0 +1,351 @@
+use crate::birthday;
+use crate::db::Database;
+use crate::dst;
+use crate::reminders;
+use crate::Error;
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, UserId};
+use serenity::client::Context as SerenityContext;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+
+// A run this much later than planned counts as missed, e.g. because the bot was down
+const MISSED_AFTER_MINUTES: i64 = 5;
+
+// What a job does when it runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+    // Warn guilds about upcoming daylight saving changes
+    DstAnnouncements,
+    // Greet the job's guild's birthdays as they fall due
+    BirthdayReminders,
+    // Deliver a member's `/remindme`
+    Reminder { reminder_id: i64 },
+    // Post an admin's scheduled message
+    Announcement { channel_id: ChannelId, text: String },
+}
+
+impl JobKind {
+    pub fn describe(&self) -> String {
+        match self {
+            JobKind::DstAnnouncements => "Daylight saving announcements".to_owned(),
+            JobKind::BirthdayReminders => "Birthday reminders".to_owned(),
+            JobKind::Reminder { reminder_id } => format!("Reminder #{}", reminder_id),
+            JobKind::Announcement { channel_id, text } => {
+                // Enough of the message to recognise it in a list
+                let preview: String = text.chars().take(60).collect();
+                let ellipsis = if preview.len() < text.len() {
+                    "…"
+                } else {
+                    ""
+                };
+                format!(
+                    "Announcement in <#{}>: \"{}{}\"",
+                    channel_id, preview, ellipsis
+                )
+            }
+        }
+    }
+}
+
+// When a job runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schedule {
+    Once,
+    Interval {
+        seconds: i64,
+    },
+    // Standard five-field cron expression, read as wall-clock time in `timezone`
+    Cron {
+        expression: String,
+        timezone: String,
+    },
+}
+
+impl Schedule {
+    // Check the schedule can be computed before it is saved
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Schedule::Once => Ok(()),
+            Schedule::Interval { seconds } if *seconds < 60 => {
+                Err("Jobs can't repeat more than once a minute.".to_owned())
+            }
+            Schedule::Interval { .. } => Ok(()),
+            Schedule::Cron {
+                expression,
+                timezone,
+            } => {
+                timezone
+                    .parse::<Tz>()
+                    .map_err(|_| format!("Unknown timezone `{}`.", timezone))?;
+                Cron::new(expression)
+                    .parse()
+                    .map(|_| ())
+                    .map_err(|err| format!("Invalid cron expression `{}`: {}", expression, err))
+            }
+        }
+    }
+
+    // The first run after `now`, for a job last due at `previous`.
+    // None once a job is done.
+    pub fn next_run(
+        &self,
+        previous: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        match self {
+            Schedule::Once => Ok(None),
+            Schedule::Interval { seconds } => {
+                // Stay on the original rhythm, skipping the runs that are already past
+                let every = ChronoDuration::seconds((*seconds).max(60));
+                let missed = (now - previous).num_seconds().max(0) / every.num_seconds();
+                Ok(Some(previous + every * (missed as i32 + 1)))
+            }
+            Schedule::Cron {
+                expression,
+                timezone,
+            } => {
+                let tz: Tz = timezone
+                    .parse()
+                    .map_err(|_| format!("Unknown timezone: {}", timezone))?;
+                let next = Cron::new(expression)
+                    .parse()?
+                    .find_next_occurrence(&now.with_timezone(&tz), false)?;
+                Ok(Some(next.with_timezone(&Utc)))
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Schedule::Once => "once".to_owned(),
+            Schedule::Interval { seconds } if seconds % 3600 == 0 => {
+                format!("every {} hours", seconds / 3600)
+            }
+            Schedule::Interval { seconds } => format!("every {} minutes", seconds / 60),
+            Schedule::Cron {
+                expression,
+                timezone,
+            } => format!("`{}` ({})", expression, timezone),
+        }
+    }
+}
+
+// What to do about runs missed while the bot was down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatchUp {
+    // Drop missed runs and wait for the next one
+    Skip,
+    // Run once as soon as possible, however many were missed
+    RunOnce,
+}
+
+impl CatchUp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CatchUp::Skip => "skip",
+            CatchUp::RunOnce => "run_once",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<CatchUp> {
+        match text {
+            "skip" => Some(CatchUp::Skip),
+            "run_once" => Some(CatchUp::RunOnce),
+            _ => None,
+        }
+    }
+}
+
+pub struct Job {
+    pub id: i64,
+    // Jobs owned by a feature have a unique name, so they're only scheduled once
+    pub name: Option<String>,
+    // None for jobs the bot runs for itself
+    pub guild_id: Option<GuildId>,
+    pub created_by: Option<UserId>,
+    pub kind: JobKind,
+    pub schedule: Schedule,
+    pub catch_up: CatchUp,
+    pub next_run: DateTime<Utc>,
+}
+
+// A job to be scheduled
+pub struct NewJob {
+    pub name: Option<String>,
+    pub guild_id: Option<GuildId>,
+    pub created_by: Option<UserId>,
+    pub kind: JobKind,
+    pub schedule: Schedule,
+    pub catch_up: CatchUp,
+    // Defaults to the schedule's next run from now
+    pub first_run: Option<DateTime<Utc>>,
+}
+
+// Runs jobs stored in the database when they fall due.
+// Cloning is cheap; all clones share the same runner.
+#[derive(Clone)]
+pub struct Scheduler {
+    db: Database,
+    // Wakes the runner when a job is added, so it doesn't oversleep
+    wake: Arc<Notify>,
+    // Jobs with a run in progress, which aren't started again until it finishes
+    running: Arc<Mutex<HashSet<i64>>>,
+}
+
+impl Scheduler {
+    pub fn new(db: Database) -> Self {
+        Scheduler {
+            db,
+            wake: Arc::new(Notify::new()),
+            running: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Save a job and return its ID, or None if a job with the same name is already saved
+    pub async fn add(&self, job: NewJob) -> Result<Option<i64>, Error> {
+        job.schedule.validate()?;
+        let now = Utc::now();
+        let next_run = match job.first_run {
+            Some(first_run) => first_run,
+            None => job
+                .schedule
+                .next_run(now, now)?
+                .ok_or("A one-off job needs a time to run")?,
+        };
+
+        let id = self
+            .db
+            .add_job(Job {
+                id: 0,
+                name: job.name,
+                guild_id: job.guild_id,
+                created_by: job.created_by,
+                kind: job.kind,
+                schedule: job.schedule,
+                catch_up: job.catch_up,
+                next_run,
+            })
+            .await?;
+        if id.is_some() {
+            self.wake.notify_one();
+        }
+        Ok(id)
+    }
+
+    pub async fn guild_jobs(&self, guild_id: GuildId) -> Result<Vec<Job>, Error> {
+        self.db.get_guild_jobs(guild_id).await
+    }
+
+    pub async fn named_job(&self, name: &str) -> Result<Option<Job>, Error> {
+        self.db.get_named_job(name).await
+    }
+
+    // Returns whether there was such a job in the guild
+    pub async fn cancel(&self, guild_id: GuildId, id: i64) -> Result<bool, Error> {
+        self.db.remove_guild_job(guild_id, id).await
+    }
+
+    // Returns whether there was such a job
+    pub async fn cancel_named(&self, name: &str) -> Result<bool, Error> {
+        self.db.remove_named_job(name).await
+    }
+
+    // Spawn the runner. Jobs saved before a restart pick up where they left off.
+    pub fn start(&self, ctx: SerenityContext) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = scheduler.run_due(&ctx).await {
+                    println!("Error running scheduled jobs: {:?}", err);
+                }
+
+                // Sleep until the next job, checking at least hourly, or until one is added
+                let until_next = match scheduler.db.get_next_job_run().await {
+                    Ok(Some(next_run)) => {
+                        (next_run - Utc::now()).to_std().unwrap_or(Duration::ZERO)
+                    }
+                    Ok(None) => Duration::from_secs(60 * 60),
+                    Err(err) => {
+                        println!("Error reading scheduled jobs: {:?}", err);
+                        Duration::from_secs(60)
+                    }
+                };
+                tokio::select! {
+                    _ = sleep(until_next.clamp(Duration::from_secs(1), Duration::from_secs(60 * 60))) => {}
+                    _ = scheduler.wake.notified() => {}
+                }
+            }
+        });
+    }
+
+    async fn run_due(&self, ctx: &SerenityContext) -> Result<(), Error> {
+        let now = Utc::now();
+        for job in self.db.get_due_jobs(now).await? {
+            let missed = now - job.next_run > ChronoDuration::minutes(MISSED_AFTER_MINUTES);
+
+            // Move the job on before running it, so a crash mid-run can't repeat it
+            match job.schedule.next_run(job.next_run, now) {
+                Ok(Some(next_run)) => self.db.set_job_next_run(job.id, next_run).await?,
+                Ok(None) => self.db.remove_job(job.id).await?,
+                Err(err) => {
+                    println!("Dropping job {} with a broken schedule: {:?}", job.id, err);
+                    self.db.remove_job(job.id).await?;
+                }
+            }
+
+            if missed && job.catch_up == CatchUp::Skip {
+                println!("Skipping missed run of job {}", job.id);
+                continue;
+            }
+
+            // A slow run, e.g. one held up by rate limits, mustn't overlap the next
+            if !self.running().insert(job.id) {
+                println!("Skipping job {} while its last run is in progress", job.id);
+                continue;
+            }
+
+            // Each run gets its own task, so a slow job doesn't hold up the rest
+            let ctx = ctx.clone();
+            let scheduler = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = scheduler.run(&ctx, &job).await {
+                    println!("Error running job {}: {:?}", job.id, err);
+                }
+                scheduler.running().remove(&job.id);
+            });
+        }
+        Ok(())
+    }
+
+    fn running(&self) -> std::sync::MutexGuard<'_, HashSet<i64>> {
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn run(&self, ctx: &SerenityContext, job: &Job) -> Result<(), Error> {
+        match &job.kind {
+            JobKind::DstAnnouncements => dst::announce_dst_transitions(ctx, &self.db).await,
+            JobKind::BirthdayReminders => {
+                let guild_id = job.guild_id.ok_or("Birthday reminders without a guild")?;
+                birthday::run_reminders(&ctx.http, &self.db, guild_id).await
+            }
+            JobKind::Reminder { reminder_id } => {
+                reminders::deliver(&ctx.http, &self.db, self, *reminder_id).await
+            }
+            JobKind::Announcement { channel_id, text } => {
+                channel_id.say(&ctx.http, text).await?;
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+// Parse a time of day such as "20:00", "8pm", "8:30 am", "20.30", "noon" or "midnight"
+pub fn parse_time_of_day(text: &str) -> Option<NaiveTime> {
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "noon" | "midday" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+
+    let (clock, meridiem) = if let Some(clock) = text.strip_suffix("am") {
+        (clock.trim_end(), Some(false))
+    } else if let Some(clock) = text.strip_suffix("pm") {
+        (clock.trim_end(), Some(true))
+    } else {
+        (text.as_str(), None)
+    };
+
+    let (hour, minute) = match clock.split_once([':', '.']) {
+        Some((hour, minute)) if minute.len() == 2 => (hour.parse().ok()?, minute.parse().ok()?),
+        Some(_) => return None,
+        None if meridiem.is_some() => (clock.parse().ok()?, 0),
+        None => return None,
+    };
+
+    let hour: u32 = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(false) => hour % 12,
+        Some(true) => hour % 12 + 12,
+        None => hour,
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+// Parse a date relative to `today`: "today", "tomorrow", "yesterday", a weekday name
+// (the next one on or after today), "2025-03-14", "14/03", "14/03/2025", "14 March" or "March 14".
+// Dates without a year are taken as the next occurrence on or after today.
+pub fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "today" | "tonight" => return Some(today),
+        "tomorrow" => return today.succ_opt(),
+        "yesterday" => return today.pred_opt(),
+        _ => {}
+    }
+
+    if let Some(weekday) = parse_weekday(&text) {
+        let days_ahead =
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        return today.checked_add_signed(Duration::days(days_ahead.into()));
+    }
+
+    for format in ["%Y-%m-%d", "%d/%m/%Y", "%d.%m.%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(&text, format) {
+            return Some(date);
+        }
+    }
+
+    let (day, month) = parse_day_month(&text)?;
+    let this_year = NaiveDate::from_ymd_opt(today.year(), month, day);
+    match this_year {
+        Some(date) if date >= today => Some(date),
+        _ => NaiveDate::from_ymd_opt(today.year() + 1, month, day),
+    }
+}
+
+// Parse a time with an optional date before or after it: "18:00", "friday 18:00",
+// "tomorrow at 8 pm" or "18:00 14/03". Returns the date only if one was given.
+pub fn parse_date_time(text: &str, today: NaiveDate) -> Option<(Option<NaiveDate>, NaiveTime)> {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .filter(|word| !matches!(word.to_lowercase().as_str(), "at" | "on"))
+        .collect();
+
+    // The time is one word, or two for "8 pm"
+    for length in 1..=words.len().min(2) {
+        let (date_first, time_last) = words.split_at(words.len() - length);
+        let (time_first, date_last) = words.split_at(length);
+        for (time, rest) in [(time_last, date_first), (time_first, date_last)] {
+            let Some(time) = parse_time_of_day(&time.join(" ")) else {
+                continue;
+            };
+            if rest.is_empty() {
+                return Some((None, time));
+            }
+            if let Some(date) = parse_date(&rest.join(" "), today) {
+                return Some((Some(date), time));
+            }
+        }
+    }
+
+    None
+}
+
+// The moment a date and time such as "friday 18:00" refers to in `tz`, as seen at `now`.
+// A bare time that has already passed today means tomorrow, and a weekday whose time has
+// passed means that day next week.
+pub fn resolve_date_time(text: &str, now: DateTime<Utc>, tz: &Tz) -> Option<DateTime<Tz>> {
+    let local_now = now.with_timezone(tz);
+    let today = local_now.date_naive();
+    let (date, time) = parse_date_time(text, today)?;
+    let names_weekday = text
+        .split_whitespace()
+        .any(|word| parse_weekday(word).is_some());
+    let date = match date {
+        Some(date) if date == today && time <= local_now.time() && names_weekday => {
+            date.checked_add_signed(Duration::days(7))?
+        }
+        Some(date) => date,
+        None if time <= local_now.time() => today.succ_opt()?,
+        None => today,
+    };
+    localize(date.and_time(time), tz)
+}
+
+pub fn parse_weekday(text: &str) -> Option<Weekday> {
+    let weekday = match text.trim().to_lowercase().as_str() {
+        "monday" | "mon" => Weekday::Mon,
+        "tuesday" | "tue" | "tues" => Weekday::Tue,
+        "wednesday" | "wed" => Weekday::Wed,
+        "thursday" | "thu" | "thurs" => Weekday::Thu,
+        "friday" | "fri" => Weekday::Fri,
+        "saturday" | "sat" => Weekday::Sat,
+        "sunday" | "sun" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+pub fn parse_month(text: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    let text = text.trim().to_lowercase();
+    if text.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|month| month.starts_with(&text))
+        .map(|index| index as u32 + 1)
+}
+
+// "14/03", "14.03", "14 march", "march 14", "14th of march"
+fn parse_day_month(text: &str) -> Option<(u32, u32)> {
+    if let Some((day, month)) = text.split_once(['/', '.']) {
+        return Some((day.parse().ok()?, month.parse().ok()?));
+    }
+
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .filter(|word| *word != "of")
+        .collect();
+    let [first, second] = words[..] else {
+        return None;
+    };
+    let parse_day = |word: &str| {
+        word.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+            .parse::<u32>()
+            .ok()
+    };
+    match (parse_day(first), parse_month(second)) {
+        (Some(day), Some(month)) => Some((day, month)),
+        _ => Some((parse_day(second)?, parse_month(first)?)),
+    }
+}
+
+// Attach a timezone to a wall-clock time.
+// Ambiguous times (clocks going back) resolve to the earlier instant, and times that
+// don't exist (clocks going forward) are moved past the gap.
+pub fn localize(naive: NaiveDateTime, tz: &Tz) -> Option<DateTime<Tz>> {
+    if let Some(datetime) = tz.from_local_datetime(&naive).earliest() {
+        return Some(datetime);
+    }
+    tz.from_local_datetime(&(naive + Duration::hours(1)))
+        .earliest()
+}
+
+pub struct FoundTime {
+    pub text: String,
+    pub date: NaiveDate,
+    pub time: NaiveTime,
+}
+
+// Find times mentioned in free text, e.g. "game at 8pm tomorrow" or "friday 18:00".
+// Only explicit clock times count: "20:00", "8pm", "8:30 am". Bare numbers are ignored
+// so ordinary chat doesn't trigger. A nearby day word (today, tonight, tomorrow or a
+// weekday) sets the date, otherwise it is `today`.
+pub fn find_times(text: &str, today: NaiveDate) -> Vec<FoundTime> {
+    // Trailing periods are kept aside, they tell abbreviations from ordinary words
+    let raw_words: Vec<&str> = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != ':' && c != '.'))
+        .collect();
+    let words: Vec<&str> = raw_words
+        .iter()
+        .map(|word| word.trim_end_matches('.'))
+        .collect();
+
+    let mut found = Vec::new();
+    let mut index = 0;
+    while index < words.len() && found.len() < 5 {
+        let word = words[index];
+
+        // Skip links and anything else that merely contains digits and colons
+        if word.contains("://") || word.is_empty() {
+            index += 1;
+            continue;
+        }
+
+        // "8 pm" is split over two words
+        let next = words.get(index + 1).map(|next| next.to_lowercase());
+        let (candidate, used) = match next.as_deref() {
+            Some(meridiem @ ("am" | "pm" | "a.m" | "p.m"))
+                if word.chars().all(|c| c.is_ascii_digit() || c == ':') =>
+            {
+                (format!("{}{}", word, meridiem.replace('.', "")), 2)
+            }
+            _ => (word.to_owned(), 1),
+        };
+
+        let is_clock = candidate.contains(':')
+            || candidate.to_lowercase().ends_with("am")
+            || candidate.to_lowercase().ends_with("pm");
+        let starts_with_digit = candidate.starts_with(|c: char| c.is_ascii_digit());
+        let time = if is_clock && starts_with_digit {
+            parse_time_of_day(&candidate)
+        } else {
+            None
+        };
+
+        let Some(time) = time else {
+            index += 1;
+            continue;
+        };
+
+        // Look a few words either side for the day it refers to
+        let window_start = index.saturating_sub(3);
+        let window_end = (index + used + 3).min(words.len());
+        let date = raw_words[window_start..window_end]
+            .iter()
+            .find_map(|word| parse_day_word(word, today))
+            .unwrap_or(today);
+
+        found.push(FoundTime {
+            text: words[index..index + used].join(" "),
+            date,
+            time,
+        });
+        index += used;
+    }
+
+    found
+}
+
+// A day word in ordinary chat, for `find_times`. Weekday abbreviations only count with
+// a period ("sat.") so that "I sat down at 5" isn't taken for Saturday.
+fn parse_day_word(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let name = word.trim_end_matches('.');
+    if name.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let abbreviated = parse_weekday(name).is_some() && !name.to_lowercase().ends_with("day");
+    if abbreviated && !word.ends_with('.') {
+        return None;
+    }
+    parse_date(name, today)
+}
+
+// Parse a duration such as "2h30m", "90m", "1.5h", "2 hours" or "1d 2h"
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().to_lowercase();
+    let mut total_seconds = 0.0;
+    let mut number = String::new();
+    let mut unit = String::new();
+    let mut parts = 0;
+
+    // Trailing space flushes the last number and unit pair
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_digit() || c == '.' {
+            if !unit.is_empty() {
+                total_seconds += unit_seconds(&unit)? * number.parse::<f64>().ok()?;
+                parts += 1;
+                number.clear();
+                unit.clear();
+            }
+            number.push(c);
+        } else if c.is_alphabetic() {
+            unit.push(c);
+        } else if !unit.is_empty() {
+            total_seconds += unit_seconds(&unit)? * number.parse::<f64>().ok()?;
+            parts += 1;
+            number.clear();
+            unit.clear();
+        }
+    }
+
+    if parts == 0 || !number.is_empty() || !total_seconds.is_finite() || total_seconds <= 0.0 {
+        return None;
+    }
+    // None past what chrono can represent, e.g. "99999999999999999d"
+    Duration::try_seconds(total_seconds.round() as i64)
+}
+
+fn unit_seconds(unit: &str) -> Option<f64> {
+    let seconds = match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60.0 * 60.0,
+        "d" | "day" | "days" => 24.0 * 60.0 * 60.0,
+        "w" | "week" | "weeks" => 7.0 * 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_am_and_pm() {
+        assert_eq!(parse_time_of_day("8pm"), Some(time(20, 0)));
+        assert_eq!(parse_time_of_day("8:30 am"), Some(time(8, 30)));
+        assert_eq!(parse_time_of_day("12am"), Some(time(0, 0)));
+        assert_eq!(parse_time_of_day("12pm"), Some(time(12, 0)));
+        assert_eq!(parse_time_of_day("12:45 AM"), Some(time(0, 45)));
+        assert_eq!(parse_time_of_day("0am"), None);
+        assert_eq!(parse_time_of_day("13pm"), None);
+    }
+
+    #[test]
+    fn parses_24_hour_times() {
+        assert_eq!(parse_time_of_day("20:00"), Some(time(20, 0)));
+        assert_eq!(parse_time_of_day("20.30"), Some(time(20, 30)));
+        assert_eq!(parse_time_of_day("00:00"), Some(time(0, 0)));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("18:5"), None);
+        assert_eq!(parse_time_of_day("18"), None);
+        assert_eq!(parse_time_of_day("noon"), Some(time(12, 0)));
+        assert_eq!(parse_time_of_day("midnight"), Some(time(0, 0)));
+    }
+
+    #[test]
+    fn parses_dates_with_times() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let friday = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        assert_eq!(
+            parse_date_time("friday 18:00", today),
+            Some((Some(friday), time(18, 0)))
+        );
+        assert_eq!(
+            parse_date_time("18:00 on friday", today),
+            Some((Some(friday), time(18, 0)))
+        );
+        assert_eq!(
+            parse_date_time("tomorrow at 8 pm", today),
+            Some((today.succ_opt(), time(20, 0)))
+        );
+        assert_eq!(parse_date_time("18:00", today), Some((None, time(18, 0))));
+        assert_eq!(parse_date_time("someday 18:00", today), None);
+    }
+
+    #[test]
+    fn resolves_passed_times_to_the_next_occurrence() {
+        // Friday 16 October 2026, 19:00 UTC
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 19, 0, 0).unwrap();
+        let resolve = |text| resolve_date_time(text, now, &Tz::UTC).unwrap().date_naive();
+        let friday = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+
+        // A weekday whose time has passed means next week
+        assert_eq!(resolve("friday 18:00"), friday + Duration::days(7));
+        assert_eq!(resolve("friday 20:00"), friday);
+        // A bare time that has passed means tomorrow
+        assert_eq!(resolve("18:00"), friday.succ_opt().unwrap());
+        assert_eq!(resolve("20:00"), friday);
+        // An explicit date is taken as given, even if it has passed
+        assert_eq!(resolve("today 18:00"), friday);
+    }
+
+    #[test]
+    fn resolves_times_in_the_given_timezone() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let due = resolve_date_time("18:00", now, &tz).unwrap();
+        assert_eq!(
+            due.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2026, 10, 16, 16, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_compound_durations() {
+        assert_eq!(parse_duration("2h30m"), Some(Duration::minutes(150)));
+        assert_eq!(parse_duration("1.5h"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("90m"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("2 hours"), Some(Duration::hours(2)));
+        assert_eq!(
+            parse_duration("1d 2h"),
+            Some(Duration::days(1) + Duration::hours(2))
+        );
+        assert_eq!(parse_duration("1w"), Some(Duration::weeks(1)));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("90"), None);
+        assert_eq!(parse_duration("2h30"), None);
+        assert_eq!(parse_duration("5 parsecs"), None);
+        assert_eq!(parse_duration("0m"), None);
+    }
+
+    #[test]
+    fn rejects_durations_past_the_representable_range() {
+        assert_eq!(parse_duration("99999999999999999d"), None);
+        assert_eq!(parse_duration(&format!("{}d", "9".repeat(400))), None);
+        assert!(parse_duration("10000w").is_some());
+    }
+
+    #[test]
+    fn finds_times_in_chat() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let found = find_times("game at 8pm tomorrow", today);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].time, time(20, 0));
+        assert_eq!(found[0].date, today.succ_opt().unwrap());
+
+        // Abbreviations only count as weekdays with a period
+        assert_eq!(find_times("I sat down at 5pm", today)[0].date, today);
+        assert_eq!(
+            find_times("game sat. at 5pm", today)[0].date.weekday(),
+            Weekday::Sat
+        );
+
+        assert!(find_times("room 20 at https://example.com/12:30", today).is_empty());
+        assert!(find_times("I have 3 cats", today).is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::sanitize;
+use crate::utils;
+use crate::Error;
+
+use async_trait::async_trait;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serenity::all::{CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, Timestamp};
+use std::time::Duration;
+use tokio::time::timeout;
+
+pub const DEFAULT_API_URL: &str = "https://api.fxtwitter.com";
+// Replies wait on these, so a slow API or avatar host mustn't hold them up for long
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+const AVATAR_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Deserialize)]
+pub struct Tweet {
+    pub url: String,
+    #[serde(default)]
+    pub text: String,
+    pub author: TweetAuthor,
+    #[serde(default)]
+    pub replies: u64,
+    #[serde(default)]
+    pub retweets: u64,
+    #[serde(default)]
+    pub likes: u64,
+    pub views: Option<u64>,
+    pub created_timestamp: Option<i64>,
+    pub media: Option<TweetMedia>,
+    pub quote: Option<Box<Tweet>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetAuthor {
+    pub name: String,
+    pub screen_name: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TweetMedia {
+    #[serde(default)]
+    pub photos: Vec<TweetPhoto>,
+    #[serde(default)]
+    pub videos: Vec<TweetVideo>,
+    // Several photos combined into one image
+    pub mosaic: Option<TweetMosaic>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetPhoto {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetVideo {
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetMosaic {
+    pub formats: TweetMosaicFormats,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetMosaicFormats {
+    pub jpeg: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TweetResponse {
+    tweet: Option<Tweet>,
+}
+
+// Where tweets come from. `FxTwitterClient` is the only source so far.
+#[async_trait]
+pub trait TweetApi: Send + Sync {
+    // None if the post doesn't exist or is private
+    async fn get_tweet(&self, screen_name: &str, id: &str) -> Result<Option<Tweet>, Error>;
+}
+
+// Client for the fxtwitter API, or anything serving the same JSON, e.g. api.fixupx.com
+pub struct FxTwitterClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl FxTwitterClient {
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        Ok(FxTwitterClient {
+            client: reqwest::Client::builder().timeout(API_TIMEOUT).build()?,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+#[async_trait]
+impl TweetApi for FxTwitterClient {
+    async fn get_tweet(&self, screen_name: &str, id: &str) -> Result<Option<Tweet>, Error> {
+        let url = format!("{}/{}/status/{}", self.base_url, screen_name, id);
+        let response = self.client.get(url).send().await?;
+        // The API mirrors Twitter's own answer, e.g. 404 for deleted and 401 for private posts
+        if matches!(
+            response.status(),
+            StatusCode::NOT_FOUND | StatusCode::UNAUTHORIZED
+        ) {
+            return Ok(None);
+        }
+        let text = response.error_for_status()?.text().await?;
+        let response: TweetResponse = serde_json::from_str(&text)?;
+        Ok(response.tweet)
+    }
+}
+
+// The author's handle and post ID of a twitter/x post link, following redirects first
+pub fn tweet_path(link: &str) -> Option<(String, String)> {
+    let url = Url::parse(link).ok()?;
+    let url = sanitize::sanitize(&url);
+    let host = url.host_str()?;
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("mobile."))
+        .unwrap_or(host);
+    if !matches!(host, "x.com" | "twitter.com") {
+        return None;
+    }
+
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    match segments[..] {
+        [screen_name, "status", id, ..]
+            if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            Some((screen_name.to_owned(), id.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+fn quote_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The image to show for a post: its photo, its photos combined, or a video thumbnail
+fn preview_image(media: &TweetMedia) -> Option<&str> {
+    match &media.photos[..] {
+        [] => media
+            .videos
+            .first()
+            .and_then(|video| video.thumbnail_url.as_deref()),
+        [photo] => Some(&photo.url),
+        [first, ..] => media
+            .mosaic
+            .as_ref()
+            .and_then(|mosaic| mosaic.formats.jpeg.as_deref())
+            .or(Some(&first.url)),
+    }
+}
+
+// An embed like Twitter's own: author, text, media, quoted post and engagement counts.
+// Coloured after the author's avatar.
+pub async fn build_tweet_embed(tweet: &Tweet) -> CreateEmbed {
+    let mut author = CreateEmbedAuthor::new(format!(
+        "{} (@{})",
+        tweet.author.name, tweet.author.screen_name
+    ))
+    .url(format!("https://x.com/{}", tweet.author.screen_name));
+    if let Some(avatar_url) = &tweet.author.avatar_url {
+        author = author.icon_url(avatar_url);
+    }
+
+    let mut description = tweet.text.clone();
+    let media = tweet.media.as_ref();
+    if let Some(video) = media.and_then(|media| media.videos.first()) {
+        description.push_str(&format!("\n\n[▶️ Watch the video]({})", video.url));
+    }
+    if let Some(quote) = &tweet.quote {
+        description.push_str(&format!(
+            "\n\n> **Quoting [{} (@{})]({})**\n{}",
+            quote.author.name,
+            quote.author.screen_name,
+            quote.url,
+            quote_lines(&quote.text)
+        ));
+    }
+    // Embed descriptions are capped at 4096 characters
+    if description.chars().count() > 4000 {
+        description = description.chars().take(4000).collect::<String>() + "…";
+    }
+
+    let mut footer = format!(
+        "💬 {}   🔁 {}   ❤️ {}",
+        utils::format_currency(tweet.replies),
+        utils::format_currency(tweet.retweets),
+        utils::format_currency(tweet.likes)
+    );
+    if let Some(views) = tweet.views {
+        footer.push_str(&format!("   👁️ {}", utils::format_currency(views)));
+    }
+
+    let mut embed = CreateEmbed::default()
+        .author(author)
+        .url(&tweet.url)
+        .description(description)
+        .footer(CreateEmbedFooter::new(footer));
+
+    // The quoted post's media if the post has none of its own
+    let image = media
+        .and_then(preview_image)
+        .or_else(|| tweet.quote.as_ref()?.media.as_ref().and_then(preview_image));
+    if let Some(image) = image {
+        embed = embed.image(image);
+    }
+
+    if let Some(timestamp) = tweet
+        .created_timestamp
+        .and_then(|timestamp| Timestamp::from_unix_timestamp(timestamp).ok())
+    {
+        embed = embed.timestamp(timestamp);
+    }
+
+    if let Some(avatar_url) = &tweet.author.avatar_url {
+        if let Ok(Ok(primary_color)) =
+            timeout(AVATAR_TIMEOUT, utils::get_image_primary_color(avatar_url)).await
+        {
+            embed = embed.color(primary_color);
+        }
+    }
+
+    embed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    // Answers a single request with `status` and `body`, returning the base URL and the
+    // request line it was sent
+    fn serve_once(status: &'static str, body: String) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // Skip the headers, there is no body on a GET
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            request_line.trim_end().to_owned()
+        });
+        (base_url, handle)
+    }
+
+    fn tweet_json(text: &str) -> Value {
+        json!({
+            "url": "https://x.com/jack/status/20",
+            "text": text,
+            "author": { "name": "jack", "screen_name": "jack" },
+            "replies": 1,
+            "retweets": 2,
+            "likes": 3,
+        })
+    }
+
+    fn tweet(value: Value) -> Tweet {
+        serde_json::from_value(value).unwrap()
+    }
+
+    async fn embed_json(tweet: &Tweet) -> Value {
+        serde_json::to_value(build_tweet_embed(tweet).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_and_private_posts_are_none() {
+        for status in ["404 Not Found", "401 Unauthorized"] {
+            let (base_url, server) = serve_once(status, r#"{"code":404}"#.to_owned());
+            let client = FxTwitterClient::new(&base_url).unwrap();
+            assert!(client.get_tweet("jack", "20").await.unwrap().is_none());
+            server.join().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_errors() {
+        let (base_url, server) = serve_once("500 Internal Server Error", String::new());
+        let client = FxTwitterClient::new(&base_url).unwrap();
+        assert!(client.get_tweet("jack", "20").await.is_err());
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn parses_posts() {
+        let mut body = tweet_json("just setting up my twttr");
+        body["views"] = json!(100);
+        body["created_timestamp"] = json!(1142974214);
+        body["media"] = json!({ "photos": [{ "url": "https://pbs.twimg.com/a.jpg" }] });
+        let (base_url, server) = serve_once("200 OK", json!({ "tweet": body }).to_string());
+
+        let client = FxTwitterClient::new(&format!("{}/", base_url)).unwrap();
+        let tweet = client.get_tweet("jack", "20").await.unwrap().unwrap();
+        assert_eq!(server.join().unwrap(), "GET /jack/status/20 HTTP/1.1");
+
+        assert_eq!(tweet.text, "just setting up my twttr");
+        assert_eq!(tweet.author.screen_name, "jack");
+        assert_eq!((tweet.replies, tweet.retweets, tweet.likes), (1, 2, 3));
+        assert_eq!(tweet.views, Some(100));
+        assert_eq!(tweet.created_timestamp, Some(1142974214));
+        assert_eq!(
+            tweet.media.unwrap().photos[0].url,
+            "https://pbs.twimg.com/a.jpg"
+        );
+        assert!(tweet.quote.is_none());
+    }
+
+    #[tokio::test]
+    async fn quotes_the_quoted_post() {
+        let mut value = tweet_json("look at this");
+        value["quote"] = json!({
+            "url": "https://x.com/ev/status/21",
+            "text": "first line\nsecond line",
+            "author": { "name": "Ev", "screen_name": "ev" },
+        });
+        let embed = embed_json(&tweet(value)).await;
+
+        assert_eq!(
+            embed["description"],
+            "look at this\n\n> **Quoting [Ev (@ev)](https://x.com/ev/status/21)**\n> first line\n> second line"
+        );
+        assert_eq!(embed["author"]["name"], "jack (@jack)");
+        assert_eq!(embed["footer"]["text"], "💬 1   🔁 2   ❤️ 3");
+    }
+
+    #[tokio::test]
+    async fn shows_the_posts_own_image_first() {
+        let mut value = tweet_json("");
+        value["media"] = json!({ "photos": [{ "url": "https://pbs.twimg.com/own.jpg" }] });
+        value["quote"] = json!({
+            "url": "https://x.com/ev/status/21",
+            "author": { "name": "Ev", "screen_name": "ev" },
+            "media": { "photos": [{ "url": "https://pbs.twimg.com/quoted.jpg" }] },
+        });
+        let embed = embed_json(&tweet(value)).await;
+        assert_eq!(embed["image"]["url"], "https://pbs.twimg.com/own.jpg");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_quoted_posts_image() {
+        let mut value = tweet_json("");
+        value["quote"] = json!({
+            "url": "https://x.com/ev/status/21",
+            "author": { "name": "Ev", "screen_name": "ev" },
+            "media": {
+                "videos": [{
+                    "url": "https://video.twimg.com/v.mp4",
+                    "thumbnail_url": "https://pbs.twimg.com/thumb.jpg",
+                }],
+            },
+        });
+        let embed = embed_json(&tweet(value)).await;
+        assert_eq!(embed["image"]["url"], "https://pbs.twimg.com/thumb.jpg");
+    }
+
+    #[tokio::test]
+    async fn truncates_long_posts() {
+        let embed = embed_json(&tweet(tweet_json(&"é".repeat(5000)))).await;
+        let description = embed["description"].as_str().unwrap();
+        assert_eq!(description.chars().count(), 4001);
+        assert!(description.ends_with("é…"));
+
+        let embed = embed_json(&tweet(tweet_json(&"é".repeat(4000)))).await;
+        assert_eq!(embed["description"], "é".repeat(4000));
+    }
+}
@@ -1,6 +1,13 @@
-use crate::Error;
+use crate::{Context, Error};
 
+use poise::CreateReply;
 use reqwest::Client as ReqwestClient;
+use serenity::all::{
+    ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, Http, Member, UserId,
+};
+use std::collections::HashMap;
+use std::time::Duration;
 
 extern crate color_thief;
 extern crate image;
@@ -30,3 +37,119 @@ pub async fn get_image_primary_color(url: &str) -> Result<(u8, u8, u8), Error> {
     let primary_color = palette.first().ok_or("No primary color found")?;
     Ok((primary_color.r, primary_color.g, primary_color.b))
 }
+
+// Discord renders `<t:unix:style>` in each reader's own timezone.
+// Styles: t, T, d, D, f, F and R (relative).
+pub fn discord_timestamp(unix: i64, style: char) -> String {
+    format!("<t:{}:{}>", unix, style)
+}
+
+// User IDs from mentions such as "<@123> <@!456>"
+pub fn parse_user_mentions(text: &str) -> Vec<UserId> {
+    let mut user_ids = Vec::new();
+    for part in text.split('<').skip(1) {
+        let Some(mention) = part.split('>').next() else {
+            continue;
+        };
+        let Some(id) = mention.strip_prefix('@') else {
+            continue;
+        };
+        let Ok(id) = id.trim_start_matches('!').parse::<u64>() else {
+            continue;
+        };
+        if id == 0 {
+            continue;
+        }
+        let user_id = UserId::new(id);
+        if !user_ids.contains(&user_id) {
+            user_ids.push(user_id);
+        }
+    }
+    user_ids
+}
+
+// Every member of a guild, a thousand per request, rather than one request per member.
+// Needs the server members intent.
+pub async fn guild_members(
+    http: impl AsRef<Http>,
+    guild_id: GuildId,
+) -> Result<HashMap<UserId, Member>, Error> {
+    const PAGE_SIZE: usize = 1000;
+    let mut members = HashMap::new();
+    let mut after = None;
+    loop {
+        let page = guild_id
+            .members(&http, Some(PAGE_SIZE as u64), after)
+            .await?;
+        let last_page = page.len() < PAGE_SIZE;
+        after = page.last().map(|member| member.user.id);
+        members.extend(page.into_iter().map(|member| (member.user.id, member)));
+        if last_page {
+            return Ok(members);
+        }
+    }
+}
+
+// Show embeds one at a time with previous/next buttons.
+// The buttons are removed once nobody has used them for ten minutes.
+pub async fn paginate_embeds(ctx: Context<'_>, pages: Vec<CreateEmbed>) -> Result<(), Error> {
+    let Some(first) = pages.first() else {
+        return Ok(());
+    };
+    if pages.len() == 1 {
+        ctx.send(CreateReply::default().embed(first.clone()))
+            .await?;
+        return Ok(());
+    }
+
+    let prev_button_id = format!("{}-prev", ctx.id());
+    let next_button_id = format!("{}-next", ctx.id());
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&prev_button_id).emoji('◀'),
+        CreateButton::new(&next_button_id).emoji('▶'),
+    ]);
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(first.clone())
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    let mut current_page = 0;
+    let ctx_id = ctx.id();
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(10 * 60))
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1) % pages.len();
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.checked_sub(1).unwrap_or(pages.len() - 1);
+        } else {
+            continue;
+        }
+
+        press
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().embed(pages[current_page].clone()),
+                ),
+            )
+            .await?;
+    }
+
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(pages[current_page].clone())
+                .components(Vec::new()),
+        )
+        .await?;
+
+    Ok(())
+}
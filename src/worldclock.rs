@@ -0,0 +1,101 @@
+use crate::Error;
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+use chrono_tz::Tz;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::f32::consts::PI;
+use std::io::Cursor;
+
+const AVATAR_SIZE: u32 = 32;
+const ROW_HEIGHT: u32 = 40;
+const LEFT_MARGIN: u32 = 48;
+const TOP_MARGIN: u32 = 16;
+const PIXELS_PER_HOUR: u32 = 30;
+const BAND_WIDTH: u32 = 24 * PIXELS_PER_HOUR;
+
+const BACKGROUND: Rgba<u8> = Rgba([43, 45, 49, 255]);
+const NIGHT: [f32; 3] = [24.0, 32.0, 72.0];
+const DAY: [f32; 3] = [250.0, 214.0, 110.0];
+const GRID: Rgba<u8> = Rgba([128, 128, 128, 255]);
+const NOW_MARKER: Rgba<u8> = Rgba([237, 66, 69, 255]);
+
+pub struct ClockRow {
+    pub tz: Tz,
+    // Already-downloaded avatar image bytes, if any
+    pub avatar: Option<Vec<u8>>,
+}
+
+// Shade for a local hour: darkest at midnight, brightest at noon
+fn daylight_color(local_hour: f32) -> Rgba<u8> {
+    let light = (1.0 - (2.0 * PI * local_hour / 24.0).cos()) / 2.0;
+    // Sharpen the curve so evenings and early mornings read as night
+    let light = light.powf(1.5);
+    let channel = |index: usize| (NIGHT[index] + (DAY[index] - NIGHT[index]) * light) as u8;
+    Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+// Render a 24 hour band per row, centred on `now`. Each column is one moment in
+// time, coloured by how far into their day that row's member is at that moment.
+// Returns PNG bytes.
+pub fn render(rows: &[ClockRow], now: DateTime<Utc>) -> Result<Vec<u8>, Error> {
+    let width = LEFT_MARGIN + BAND_WIDTH + 8;
+    let height = TOP_MARGIN + ROW_HEIGHT * rows.len() as u32 + 8;
+    let mut canvas = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    let start = now - Duration::hours(12);
+
+    for (index, row) in rows.iter().enumerate() {
+        let top = TOP_MARGIN + ROW_HEIGHT * index as u32;
+
+        if let Some(avatar) = row
+            .avatar
+            .as_deref()
+            .and_then(|bytes| image::load_from_memory(bytes).ok())
+        {
+            let avatar = imageops::resize(&avatar, AVATAR_SIZE, AVATAR_SIZE, FilterType::Triangle);
+            imageops::overlay(&mut canvas, &avatar, 8, i64::from(top + 4));
+        }
+
+        let mut previous_date = None;
+        for x in 0..BAND_WIDTH {
+            let moment =
+                start + Duration::seconds(i64::from(x) * 3600 / i64::from(PIXELS_PER_HOUR));
+            let local = moment.with_timezone(&row.tz);
+            let local_hour = local.hour() as f32 + local.minute() as f32 / 60.0;
+
+            // Mark local midnight so day boundaries stand out
+            let date = local.date_naive();
+            let color = if previous_date.is_some_and(|previous| previous != date) {
+                GRID
+            } else {
+                daylight_color(local_hour)
+            };
+            previous_date = Some(date);
+
+            for y in top + 6..top + ROW_HEIGHT - 6 {
+                canvas.put_pixel(LEFT_MARGIN + x, y, color);
+            }
+        }
+    }
+
+    // Tick marks every three hours along the top
+    for hour in (0..=24).step_by(3) {
+        let x = LEFT_MARGIN + (hour * PIXELS_PER_HOUR).min(BAND_WIDTH - 1);
+        for y in 0..TOP_MARGIN - 4 {
+            canvas.put_pixel(x, y, GRID);
+        }
+    }
+
+    // The current moment sits in the middle
+    let now_x = LEFT_MARGIN + BAND_WIDTH / 2;
+    for y in 0..height {
+        for x in now_x - 1..=now_x + 1 {
+            canvas.put_pixel(x, y, NOW_MARKER);
+        }
+    }
+
+    let mut png = Cursor::new(Vec::new());
+    canvas.write_to(&mut png, ImageFormat::Png)?;
+    Ok(png.into_inner())
+}